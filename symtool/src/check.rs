@@ -0,0 +1,189 @@
+//! Finding corruption such as overlapping entries or duplicate names.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::map::{self, MapEntry, MapFile};
+
+/// A problem found in a map by [`check`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// Section of the entry with the problem.
+    pub section: String,
+    /// Start address of the entry.
+    pub addr: u32,
+    /// Name of the entry.
+    pub name: String,
+    /// What is wrong with the entry.
+    pub kind: ProblemKind,
+}
+
+/// What is wrong with an entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// The entry extends past the start of the next entry.
+    Overlap {
+        /// Name of the next entry.
+        next: String,
+        /// Number of bytes the entries share.
+        bytes: u32,
+    },
+
+    /// Unmapped bytes between this entry and the next in a code section.
+    Gap {
+        /// Name of the next entry.
+        next: String,
+        /// Number of unmapped bytes.
+        bytes: u32,
+    },
+
+    /// The entry starts before the previous entry.
+    OutOfOrder {
+        /// Name of the previous entry.
+        prev: String,
+    },
+
+    /// Another entry has the same name.
+    DuplicateName {
+        /// Start address of the first entry with the name.
+        other_addr: u32,
+    },
+
+    /// The vaddr column differs from the start address.
+    StartVaddrMismatch {
+        /// The entry's vaddr column.
+        vaddr: u32,
+    },
+
+    /// The name is not a valid C identifier.
+    InvalidName,
+
+    /// The address embedded in a placeholder name is not the entry's own address.
+    PlaceholderMismatch {
+        /// The address in the name.
+        embedded: u32,
+    },
+}
+
+impl ProblemKind {
+    /// Errors mean the map is corrupt. Gaps are only warnings, as the game has
+    /// plenty of code that Dolphin never detected.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, ProblemKind::Gap { .. })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = if self.kind.is_error() { "error" } else { "warning" };
+        write!(f, "{}: {} {:08x} {}: ", severity, self.section, self.addr, self.name)?;
+
+        match &self.kind {
+            ProblemKind::Overlap { next, bytes } => write!(f, "overlaps {} by 0x{:x} bytes", next, bytes),
+            ProblemKind::Gap { next, bytes } => write!(f, "gap of 0x{:x} bytes before {}", bytes, next),
+            ProblemKind::OutOfOrder { prev } => write!(f, "starts before the previous entry {}", prev),
+            ProblemKind::DuplicateName { other_addr } => write!(f, "name is also used at {:08x}", other_addr),
+            ProblemKind::StartVaddrMismatch { vaddr } => write!(f, "start does not match vaddr {:08x}", vaddr),
+            ProblemKind::InvalidName => write!(f, "name is not a valid identifier"),
+            ProblemKind::PlaceholderMismatch { embedded } => {
+                write!(f, "placeholder name refers to {:08x}", embedded)
+            }
+        }
+    }
+}
+
+/// Checks a map for corruption, returning problems in map order.
+pub fn check(mapfile: &MapFile) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut names = HashMap::<&str, u32>::new();
+
+    for section in mapfile.sections.iter() {
+        let mut push = |entry: &MapEntry, kind| problems.push(Problem {
+            section: section.name.clone(),
+            addr: entry.start,
+            name: entry.name.clone(),
+            kind,
+        });
+
+        for (i, entry) in section.entries.iter().enumerate() {
+            if entry.start != entry.vaddr {
+                push(entry, ProblemKind::StartVaddrMismatch { vaddr: entry.vaddr });
+            }
+
+            if !map::is_identifier(&entry.name) {
+                push(entry, ProblemKind::InvalidName);
+            }
+
+            if let Some(embedded) = map::placeholder_addr(&entry.name) && embedded != entry.start {
+                push(entry, ProblemKind::PlaceholderMismatch { embedded });
+            }
+
+            match names.get(entry.name.as_str()) {
+                Some(&other_addr) => push(entry, ProblemKind::DuplicateName { other_addr }),
+                None => { names.insert(&entry.name, entry.start); }
+            }
+
+            if i != 0 {
+                let prev = &section.entries[i-1];
+                if entry.start < prev.start {
+                    push(entry, ProblemKind::OutOfOrder { prev: prev.name.clone() });
+                }
+            }
+
+            let Some(next) = section.entries.get(i+1) else { continue };
+            if next.start < entry.start { continue } // reported as out of order
+
+            let end = entry.start as u64 + entry.size as u64;
+            let next_start = next.start as u64;
+            if end > next_start {
+                push(entry, ProblemKind::Overlap { next: next.name.clone(), bytes: (end - next_start) as u32 });
+            } else if end < next_start && section.is_code() {
+                push(entry, ProblemKind::Gap { next: next.name.clone(), bytes: (next_start - end) as u32 });
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<(String, ProblemKind)> {
+        check(&MapFile::parse(src).unwrap()).into_iter().map(|p| (p.name, p.kind)).collect()
+    }
+
+    #[test]
+    fn clean_map() {
+        let src = ".text section layout\n80003100 000030 80003100 0 memset\n80003130 0000c4 80003130 0 memset_internal\n";
+        assert_eq!(kinds(src), []);
+    }
+
+    #[test]
+    fn errors() {
+        let src = "\
+.text section layout
+80003100 000040 80003100 0 memset
+80003130 0000c4 80003130 0 memset
+80003200 000010 80003204 0 zz_80003300_
+800031f4 000010 800031f4 0 2bad
+";
+        assert_eq!(kinds(src), [
+            ("memset".into(), ProblemKind::Overlap { next: "memset".into(), bytes: 0x10 }),
+            ("memset".into(), ProblemKind::DuplicateName { other_addr: 0x80003100 }),
+            ("memset".into(), ProblemKind::Gap { next: "zz_80003300_".into(), bytes: 0x0c }),
+            ("zz_80003300_".into(), ProblemKind::StartVaddrMismatch { vaddr: 0x80003204 }),
+            ("zz_80003300_".into(), ProblemKind::PlaceholderMismatch { embedded: 0x80003300 }),
+            ("2bad".into(), ProblemKind::InvalidName),
+            ("2bad".into(), ProblemKind::OutOfOrder { prev: "zz_80003300_".into() }),
+        ]);
+    }
+
+    #[test]
+    fn gaps_only_in_code() {
+        let src = ".data section layout\n803b7240 000008 803b7240 0 a\n803b7250 000008 803b7250 0 b\n";
+        assert_eq!(kinds(src), []);
+        assert!(!ProblemKind::Gap { next: String::new(), bytes: 4 }.is_error());
+    }
+}
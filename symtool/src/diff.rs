@@ -0,0 +1,308 @@
+//! Comparing two maps entry by entry.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+
+use crate::json;
+use crate::map::{MapEntry, MapFile};
+use crate::update::Rename;
+
+/// Differences between two maps, matched by start address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MapDiff {
+    /// Entries at the same address with a different name.
+    pub renamed: Vec<Rename>,
+    /// Entries at the same address with a different size.
+    pub resized: Vec<Resized>,
+    /// Names that are at a different address in the new map.
+    pub moved: Vec<Moved>,
+    /// Entries only in the new map.
+    pub added: Vec<DiffEntry>,
+    /// Entries only in the old map.
+    pub removed: Vec<DiffEntry>,
+}
+
+/// An entry along with its section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffEntry {
+    /// Name of the entry's section.
+    pub section: String,
+    /// The entry itself.
+    pub entry: MapEntry,
+}
+
+/// A change to the size of an entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resized {
+    /// Name of the entry's section.
+    pub section: String,
+    /// Start address of the entry.
+    pub addr: u32,
+    /// Name of the entry.
+    pub name: String,
+    /// Size before the change.
+    pub old_size: u32,
+    /// Size after the change.
+    pub new_size: u32,
+}
+
+/// A name that is at a different address in the new map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Moved {
+    /// The name that moved.
+    pub name: String,
+    /// Section of the entry with the name in the old map.
+    pub old_section: String,
+    /// Start address of the entry with the name in the old map.
+    pub old_addr: u32,
+    /// Section of the entry with the name in the new map.
+    pub new_section: String,
+    /// Start address of the entry with the name in the new map.
+    pub new_addr: u32,
+}
+
+impl MapDiff {
+    /// Whether the maps have the same entries.
+    pub fn is_empty(&self) -> bool {
+        self.renamed.is_empty() && self.resized.is_empty() && self.moved.is_empty()
+            && self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Writes the diff as a JSON object with one array per kind of change.
+    ///
+    /// Addresses are written as "0x%08x" strings, sizes as numbers.
+    pub fn write_json(&self, out: &mut impl Write) -> fmt::Result {
+        out.write_str("{\n")?;
+
+        write_json_array(out, "renamed", &self.renamed, |out, r| {
+            write!(out, "{{\"section\": ")?;
+            json::write_str(out, &r.section)?;
+            write!(out, ", \"addr\": \"0x{:08x}\", \"old\": ", r.addr)?;
+            json::write_str(out, &r.old)?;
+            out.write_str(", \"new\": ")?;
+            json::write_str(out, &r.new)?;
+            out.write_char('}')
+        })?;
+        out.write_str(",\n")?;
+
+        write_json_array(out, "resized", &self.resized, |out, r| {
+            write!(out, "{{\"section\": ")?;
+            json::write_str(out, &r.section)?;
+            write!(out, ", \"addr\": \"0x{:08x}\", \"name\": ", r.addr)?;
+            json::write_str(out, &r.name)?;
+            write!(out, ", \"old_size\": {}, \"new_size\": {}}}", r.old_size, r.new_size)
+        })?;
+        out.write_str(",\n")?;
+
+        write_json_array(out, "moved", &self.moved, |out, m| {
+            write!(out, "{{\"name\": ")?;
+            json::write_str(out, &m.name)?;
+            out.write_str(", \"old_section\": ")?;
+            json::write_str(out, &m.old_section)?;
+            write!(out, ", \"old_addr\": \"0x{:08x}\", \"new_section\": ", m.old_addr)?;
+            json::write_str(out, &m.new_section)?;
+            write!(out, ", \"new_addr\": \"0x{:08x}\"}}", m.new_addr)
+        })?;
+        out.write_str(",\n")?;
+
+        let write_entry = |out: &mut dyn Write, d: &DiffEntry| {
+            write!(out, "{{\"section\": ")?;
+            json::write_str(out, &d.section)?;
+            write!(out, ", \"start\": \"0x{:08x}\", \"size\": {}, \"name\": ", d.entry.start, d.entry.size)?;
+            json::write_str(out, &d.entry.name)?;
+            out.write_char('}')
+        };
+        write_json_array(out, "added", &self.added, write_entry)?;
+        out.write_str(",\n")?;
+        write_json_array(out, "removed", &self.removed, write_entry)?;
+
+        out.write_str("\n}\n")
+    }
+}
+
+fn write_json_array<T>(
+    out: &mut impl Write,
+    key: &str,
+    items: &[T],
+    write_item: impl Fn(&mut dyn Write, &T) -> fmt::Result,
+) -> fmt::Result {
+    write!(out, "    \"{}\": [", key)?;
+    for (i, item) in items.iter().enumerate() {
+        out.write_str(if i == 0 { "\n        " } else { ",\n        " })?;
+        write_item(out, item)?;
+    }
+    if !items.is_empty() { out.write_str("\n    ")?; }
+    out.write_char(']')
+}
+
+/// Human readable output, grouped by kind of change.
+impl fmt::Display for MapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.renamed.is_empty() {
+            writeln!(f, "renamed ({}):", self.renamed.len())?;
+            for r in self.renamed.iter() {
+                writeln!(f, "    {:08x} {} {} -> {}", r.addr, r.section, r.old, r.new)?;
+            }
+        }
+
+        if !self.resized.is_empty() {
+            writeln!(f, "resized ({}):", self.resized.len())?;
+            for r in self.resized.iter() {
+                writeln!(f, "    {:08x} {} {} 0x{:x} -> 0x{:x}", r.addr, r.section, r.name, r.old_size, r.new_size)?;
+            }
+        }
+
+        if !self.moved.is_empty() {
+            writeln!(f, "moved ({}):", self.moved.len())?;
+            for m in self.moved.iter() {
+                writeln!(
+                    f, "    {} {:08x} {} -> {:08x} {}",
+                    m.name, m.old_addr, m.old_section, m.new_addr, m.new_section,
+                )?;
+            }
+        }
+
+        if !self.added.is_empty() {
+            writeln!(f, "added ({}):", self.added.len())?;
+            for d in self.added.iter() {
+                writeln!(f, "    {:08x} {} {} size 0x{:x}", d.entry.start, d.section, d.entry.name, d.entry.size)?;
+            }
+        }
+
+        if !self.removed.is_empty() {
+            writeln!(f, "removed ({}):", self.removed.len())?;
+            for d in self.removed.iter() {
+                writeln!(f, "    {:08x} {} {} size 0x{:x}", d.entry.start, d.section, d.entry.name, d.entry.size)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares two maps.
+///
+/// Entries are matched by start address. A name found at different addresses
+/// in both maps is reported as moved rather than as added and removed.
+pub fn diff(old: &MapFile, new: &MapFile) -> MapDiff {
+    let mut diff = MapDiff::default();
+
+    let old_entries = entries_by_addr(old);
+    let new_entries = entries_by_addr(new);
+
+    let by_name = |entries: &BTreeMap<u32, (&str, &MapEntry)>| {
+        let mut names = HashMap::<String, u32>::new();
+        for (&addr, (_, entry)) in entries.iter() {
+            names.insert(entry.name.clone(), addr);
+        }
+        names
+    };
+    let old_names = by_name(&old_entries);
+    let new_names = by_name(&new_entries);
+
+    for (&addr, &(section, old_entry)) in old_entries.iter() {
+        let Some(&(_, new_entry)) = new_entries.get(&addr) else {
+            if !new_names.contains_key(&old_entry.name) {
+                diff.removed.push(DiffEntry { section: section.to_string(), entry: old_entry.clone() });
+            }
+            continue
+        };
+
+        if old_entry.name != new_entry.name {
+            diff.renamed.push(Rename {
+                section: section.to_string(),
+                addr,
+                old: old_entry.name.clone(),
+                new: new_entry.name.clone(),
+            });
+        }
+
+        if old_entry.size != new_entry.size {
+            diff.resized.push(Resized {
+                section: section.to_string(),
+                addr,
+                name: new_entry.name.clone(),
+                old_size: old_entry.size,
+                new_size: new_entry.size,
+            });
+        }
+    }
+
+    for (&addr, &(section, new_entry)) in new_entries.iter() {
+        match old_names.get(&new_entry.name) {
+            Some(&old_addr) if old_addr != addr => diff.moved.push(Moved {
+                name: new_entry.name.clone(),
+                old_section: old_entries[&old_addr].0.to_string(),
+                old_addr,
+                new_section: section.to_string(),
+                new_addr: addr,
+            }),
+            Some(_) => {}
+            None if !old_entries.contains_key(&addr) => {
+                diff.added.push(DiffEntry { section: section.to_string(), entry: new_entry.clone() });
+            }
+            None => {}
+        }
+    }
+
+    diff
+}
+
+fn entries_by_addr(mapfile: &MapFile) -> BTreeMap<u32, (&str, &MapEntry)> {
+    let mut entries = BTreeMap::new();
+    for (section, entry) in mapfile.section_entries() {
+        entries.insert(entry.start, (section.name.as_str(), entry));
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80003130 0000c4 80003130 0 zz_80003130_
+800031f4 000050 800031f4 0 memcpy
+80003244 000024 80003244 0 TRK_MemCopy
+";
+
+    const NEW: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80003130 0000c8 80003130 0 memset_internal
+80003268 000030 80003268 0 memcpy
+80003300 000010 80003300 0 added
+";
+
+    #[test]
+    fn diff_by_addr() {
+        let diff = diff(&MapFile::parse(OLD).unwrap(), &MapFile::parse(NEW).unwrap());
+        assert_eq!(diff.renamed, [Rename { section: ".text".into(), addr: 0x80003130, old: "zz_80003130_".into(), new: "memset_internal".into() }]);
+        assert_eq!(diff.resized.len(), 1);
+        assert_eq!((diff.resized[0].old_size, diff.resized[0].new_size), (0xc4, 0xc8));
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!((diff.moved[0].name.as_str(), diff.moved[0].old_addr, diff.moved[0].new_addr), ("memcpy", 0x800031f4, 0x80003268));
+        let names = |entries: &[DiffEntry]| entries.iter().map(|d| d.entry.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&diff.added), ["added"]);
+        assert_eq!(names(&diff.removed), ["TRK_MemCopy"]);
+    }
+
+    #[test]
+    fn identical_maps() {
+        let mapfile = MapFile::parse(OLD).unwrap();
+        assert!(diff(&mapfile, &mapfile).is_empty());
+        assert_eq!(diff(&mapfile, &mapfile).to_string(), "");
+    }
+
+    #[test]
+    fn json_is_valid() {
+        let diff = diff(&MapFile::parse(OLD).unwrap(), &MapFile::parse(NEW).unwrap());
+        let mut out = String::new();
+        diff.write_json(&mut out).unwrap();
+        let value = json::parse(&out).unwrap();
+        assert_eq!(value.get("renamed").and_then(json::Value::as_array).map(|a| a.len()), Some(1));
+        assert_eq!(value.get("removed").and_then(json::Value::as_array).map(|a| a.len()), Some(1));
+    }
+}
@@ -0,0 +1,278 @@
+//! Reading decomp-toolkit's symbols.txt and splits.txt.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::map::{self, ParseError, ParseErrorKind, Section};
+use crate::update::UpdateRecord;
+
+// Reading decomp-toolkit's config files. symbols.txt has one symbol per line:
+//
+//     memset = .text:0x80003100; // type:function size:0x30 scope:global
+//
+// splits.txt lists the sections, then the address ranges of each object file:
+//
+//     Sections:
+//         .text       type:code align:32
+//
+//     runtime/__mem.c:
+//         .text       start:0x80003100 end:0x80003400
+
+/// The address range of a section that belongs to one object file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Split {
+    /// Source file of the object, e.g. "runtime/__mem.c".
+    pub object: String,
+    /// dtk's name for the section, e.g. ".init".
+    pub section: String,
+    /// Start address.
+    pub start: u32,
+    /// End address, exclusive.
+    pub end: u32,
+}
+
+impl Split {
+    /// Whether `addr` is within the split.
+    pub fn contains(&self, addr: u32) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+
+    /// Parses a line of the objects sidecar file, as written by `Display`.
+    pub fn parse(line: &str) -> Result<Split, ParseErrorKind> {
+        let mut columns = line.splitn(4, ' ');
+        let mut hex = |name| {
+            let column = columns.next().ok_or(ParseErrorKind::MissingColumn(name))?;
+            u32::from_str_radix(column, 16).map_err(|_| ParseErrorKind::InvalidHex(name))
+        };
+        let start = hex("start")?;
+        let end = hex("end")?;
+        let section = columns.next().ok_or(ParseErrorKind::MissingColumn("section"))?.to_string();
+        let object = columns.next().ok_or(ParseErrorKind::MissingColumn("object"))?.to_string();
+        Ok(Split { object, section, start, end })
+    }
+}
+
+/// Code ranges of the DOL, as decomp-toolkit's GALE01 config splits them. Dolphin maps put
+/// all code in .text, where dtk has the startup code and runtime helpers from 0x80003100 in
+/// .init, and .text from 0x80005940. Code below .init, such as the exception vectors from
+/// 0x80000100, is copied there at runtime and belongs to no DOL section.
+///
+/// Only the default for maps without code splits, see [`code_ranges`].
+pub const CODE_RANGES: &[(&str, Range<u32>)] = &[
+    (".init", 0x80003100..0x80005940),
+    (".text", 0x80005940..MEM_END),
+];
+
+/// End of the GameCube's main memory.
+const MEM_END: u32 = 0x81800000;
+
+/// Returns the range of each dtk code section. A code section with splits starts at its first
+/// split and ends where the next one starts, the last one at the end of memory. Without any
+/// splits of .init or .text, the ranges are GALE01's [`CODE_RANGES`].
+pub fn code_ranges(splits: &[Split]) -> Vec<(String, Range<u32>)> {
+    let mut starts = map::CODE_SECTIONS.iter()
+        .filter_map(|&name| Some((splits.iter().filter(|s| s.section == name).map(|s| s.start).min()?, name)))
+        .collect::<Vec<_>>();
+    if starts.is_empty() {
+        return CODE_RANGES.iter().map(|(name, range)| (name.to_string(), range.clone())).collect();
+    }
+    starts.sort_unstable();
+
+    starts.iter().enumerate().map(|(i, &(start, name))| {
+        let end = starts.get(i+1).map_or(MEM_END, |&(next, _)| next);
+        (name.to_string(), start..end)
+    }).collect()
+}
+
+/// Returns dtk's name for the section of the entry at `addr` in map section `section`:
+/// the section of the split covering it if any, else the code range covering it for code,
+/// else the map's own section name. `None` if code is outside every code range.
+pub fn section_at<'a>(
+    section: &'a Section,
+    addr: u32,
+    splits: &'a [Split],
+    code_ranges: &'a [(String, Range<u32>)],
+) -> Option<&'a str> {
+    if let Some(split) = splits.iter().find(|s| s.contains(addr)) { return Some(&split.section) }
+    if !section.is_code() { return Some(&section.name) }
+    code_ranges.iter().find(|(_, range)| range.contains(&addr)).map(|(name, _)| name.as_str())
+}
+
+/// Parses symbols.txt into update records carrying each symbol's section, size and alignment.
+///
+/// Only data sections are kept. dtk splits code into .init and .text where Dolphin maps have
+/// only .text, see [`code_ranges`], so code goes to whichever section holds its address.
+pub fn parse_symbols(src: &str) -> Result<Vec<UpdateRecord>, ParseError> {
+    let mut records = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let err = |kind| ParseError { line: i + 1, kind };
+        let (symbol, attrs) = line.split_once("//").unwrap_or((line, ""));
+        if symbol.trim().is_empty() { continue }
+
+        let (name, location) = symbol.split_once('=').ok_or(err(ParseErrorKind::MissingColumn("address")))?;
+        let name = name.trim().trim_matches('"');
+        let location = location.trim().trim_end_matches(';');
+        let (section, addr) = location.rsplit_once(':').ok_or(err(ParseErrorKind::MissingColumn("section")))?;
+        let addr = parse_hex(addr).ok_or(err(ParseErrorKind::InvalidHex("address")))?;
+        let section = section.trim();
+        let section = (!map::CODE_SECTIONS.contains(&section)).then(|| section.to_string());
+
+        let mut size = None;
+        let mut align = None;
+        for attr in attrs.split_whitespace() {
+            if let Some(value) = attr.strip_prefix("size:") {
+                size = Some(parse_hex(value).ok_or(err(ParseErrorKind::InvalidHex("size")))?);
+            } else if let Some(value) = attr.strip_prefix("align:") {
+                align = Some(value.parse().map_err(|_| err(ParseErrorKind::InvalidAlign))?);
+            }
+        }
+
+        records.push(UpdateRecord {
+            addr,
+            name: name.to_string(),
+            section,
+            size,
+            vaddr: None,
+            align,
+        });
+    }
+
+    Ok(records)
+}
+
+/// Parses the object file address ranges of splits.txt.
+pub fn parse_splits(src: &str) -> Result<Vec<Split>, ParseError> {
+    let mut splits = Vec::new();
+    let mut object = None::<&str>;
+
+    for (i, line) in src.lines().enumerate() {
+        let err = |kind| ParseError { line: i + 1, kind };
+        let line = line.split_once("//").map_or(line, |(line, _)| line);
+        if line.trim().is_empty() { continue }
+
+        // unindented lines start a block, indented lines belong to it
+        if !line.starts_with([' ', '\t']) {
+            object = match line.trim().trim_end_matches(':') {
+                "Sections" => None,
+                name => Some(name),
+            };
+            continue;
+        }
+        let Some(object) = object else { continue };
+
+        let mut columns = line.split_whitespace();
+        let section = columns.next().ok_or(err(ParseErrorKind::MissingColumn("section")))?;
+        let mut start = None;
+        let mut end = None;
+        for attr in columns {
+            if let Some(value) = attr.strip_prefix("start:") {
+                start = Some(parse_hex(value).ok_or(err(ParseErrorKind::InvalidHex("start")))?);
+            } else if let Some(value) = attr.strip_prefix("end:") {
+                end = Some(parse_hex(value).ok_or(err(ParseErrorKind::InvalidHex("end")))?);
+            }
+        }
+
+        splits.push(Split {
+            object: object.to_string(),
+            section: section.to_string(),
+            start: start.ok_or(err(ParseErrorKind::MissingColumn("start")))?,
+            end: end.ok_or(err(ParseErrorKind::MissingColumn("end")))?,
+        });
+    }
+
+    Ok(splits)
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    let s = s.trim();
+    u32::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16).ok()
+}
+
+/// Writes the split as a line of the objects sidecar file, e.g.
+/// "80003100 80003400 .text runtime/__mem.c".
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:08x} {:08x} {} {}", self.start, self.end, self.section, self.object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols() {
+        let src = "\
+memset = .init:0x80003100; // type:function size:0x30 scope:global
+fn_80005B64 = .text:0x80005B64; // type:function size:0x4C align:4
+
+\"quoted\" = .sdata:0x804D36A0; // type:object size:0x4
+";
+        let records = parse_symbols(src).unwrap();
+        let summary = records.iter().map(|r| (r.addr, r.name.as_str(), r.section.as_deref(), r.size, r.align)).collect::<Vec<_>>();
+        assert_eq!(summary, [
+            (0x80003100, "memset", None, Some(0x30), None),
+            (0x80005b64, "fn_80005B64", None, Some(0x4c), Some(4)),
+            (0x804d36a0, "quoted", Some(".sdata"), Some(4), None),
+        ]);
+
+        let err = parse_symbols("memset .init:0x80003100;").unwrap_err();
+        assert_eq!(err, ParseError { line: 1, kind: ParseErrorKind::MissingColumn("address") });
+    }
+
+    #[test]
+    fn splits() {
+        let src = "\
+Sections:
+\t.init       type:code align:4
+\t.text       type:code align:32
+
+runtime/__mem.c:
+\t.init       start:0x80003100 end:0x80003244 // memset
+
+main.c:
+\t.text       start:0x80005940 end:0x80005B64
+\t.data       start:0x803B7240 end:0x803B7250
+";
+        let splits = parse_splits(src).unwrap();
+        assert_eq!(splits.len(), 3);
+        assert_eq!(splits[0], Split { object: "runtime/__mem.c".into(), section: ".init".into(), start: 0x80003100, end: 0x80003244 });
+        assert_eq!(splits[2].object, "main.c");
+
+        // the objects sidecar round trip
+        let line = splits[0].to_string();
+        assert_eq!(line, "80003100 80003244 .init runtime/__mem.c\n");
+        assert_eq!(Split::parse(line.trim_end()), Ok(splits[0].clone()));
+    }
+
+    #[test]
+    fn sections() {
+        let text = Section { name: ".text".into(), entries: Vec::new() };
+        let data = Section { name: ".data".into(), entries: Vec::new() };
+        let code = code_ranges(&[]);
+        assert_eq!(section_at(&text, 0x80000100, &[], &code), None);
+        assert_eq!(section_at(&text, 0x80003100, &[], &code), Some(".init"));
+        assert_eq!(section_at(&text, 0x80005b64, &[], &code), Some(".text"));
+        assert_eq!(section_at(&data, 0x803b7240, &[], &code), Some(".data"));
+    }
+
+    #[test]
+    fn code_ranges_from_splits() {
+        // another DOL, whose .text starts at 0x80006000
+        let splits = parse_splits("\
+init.c:
+\t.init       start:0x80003400 end:0x80003500
+main.c:
+\t.text       start:0x80006000 end:0x80006100
+\t.data       start:0x803b7240 end:0x803b7250
+").unwrap();
+        let code = code_ranges(&splits);
+        assert_eq!(code, [(".init".to_string(), 0x80003400..0x80006000), (".text".to_string(), 0x80006000..MEM_END)]);
+
+        let text = Section { name: ".text".into(), entries: Vec::new() };
+        assert_eq!(section_at(&text, 0x80003100, &splits, &code), None);
+        assert_eq!(section_at(&text, 0x80005940, &splits, &code), Some(".init"));
+        assert_eq!(section_at(&text, 0x80006200, &splits, &code), Some(".text"));
+    }
+}
@@ -0,0 +1,297 @@
+//! ELF symbol tables for standard tools.
+
+use crate::map::MapFile;
+
+// A symbols-only ELF32 big-endian PowerPC executable, for tools that read ELF symbol tables:
+//
+//     (gdb) add-symbol-file GALE01.elf
+//     powerpc-eabi-ld --just-symbols=GALE01.elf ...
+//
+// Each map section with entries gets a NOBITS section header spanning its entries, so the
+// file holds no code or data, only .symtab, .strtab and .shstrtab.
+
+const EM_PPC: u16 = 20;
+const ET_EXEC: u16 = 2;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u32 = 0x1;
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+
+const SHN_ABS: u16 = 0xfff1;
+
+const STB_GLOBAL: u8 = 1;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+const EHDR_SIZE: usize = 52;
+const SHDR_SIZE: usize = 40;
+const SYM_SIZE: usize = 16;
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    addr: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entsize: u32,
+}
+
+/// Returns an ELF file with a symbol for every map entry: `STT_FUNC` in code sections,
+/// `STT_OBJECT` in others, sized like the entry.
+///
+/// Symbols are placed in their sections, or are `SHN_ABS` if `absolute` is set.
+pub fn write_elf(mapfile: &MapFile, absolute: bool) -> Vec<u8> {
+    let mut shstrtab = StringTable::new();
+    let mut strtab = StringTable::new();
+    let mut symtab = Vec::<u8>::new();
+    let mut headers = Vec::<SectionHeader>::new();
+
+    // null section and symbol
+    headers.push(SectionHeader { name: 0, kind: 0, flags: 0, addr: 0, offset: 0, size: 0, link: 0, info: 0, align: 0, entsize: 0 });
+    symtab.extend_from_slice(&[0; SYM_SIZE]);
+
+    for section in mapfile.sections.iter() {
+        let Some(start) = section.entries.iter().map(|e| e.start).min() else { continue };
+        let end = section.entries.iter().map(|e| e.start as u64 + e.size as u64).max().unwrap_or(start as u64);
+
+        let shndx = headers.len() as u16;
+        let flags = if section.is_code() { SHF_ALLOC | SHF_EXECINSTR } else { SHF_ALLOC | SHF_WRITE };
+        headers.push(SectionHeader {
+            name: shstrtab.add(&section.name),
+            kind: SHT_NOBITS,
+            flags,
+            addr: start,
+            offset: 0,
+            size: (end - start as u64) as u32,
+            link: 0,
+            info: 0,
+            align: 4,
+            entsize: 0,
+        });
+
+        let kind = if section.is_code() { STT_FUNC } else { STT_OBJECT };
+        for entry in section.entries.iter() {
+            symtab.extend_from_slice(&strtab.add(&entry.name).to_be_bytes());
+            symtab.extend_from_slice(&entry.start.to_be_bytes());
+            symtab.extend_from_slice(&entry.size.to_be_bytes());
+            symtab.push(STB_GLOBAL << 4 | kind);
+            symtab.push(0);
+            symtab.extend_from_slice(&(if absolute { SHN_ABS } else { shndx }).to_be_bytes());
+        }
+    }
+
+    let symtab_index = headers.len() as u32;
+    let symtab_name = shstrtab.add(".symtab");
+    let strtab_name = shstrtab.add(".strtab");
+    let shstrtab_name = shstrtab.add(".shstrtab");
+
+    // file layout: header, .symtab, .strtab, .shstrtab, section headers
+    let symtab_offset = EHDR_SIZE;
+    let strtab_offset = symtab_offset + symtab.len();
+    let shstrtab_offset = strtab_offset + strtab.bytes.len();
+    let shoff = (shstrtab_offset + shstrtab.bytes.len()).next_multiple_of(4);
+
+    headers.push(SectionHeader {
+        name: symtab_name,
+        kind: SHT_SYMTAB,
+        flags: 0,
+        addr: 0,
+        offset: symtab_offset as u32,
+        size: symtab.len() as u32,
+        link: symtab_index + 1,
+        // index of the first global symbol, all but the null symbol are global
+        info: 1,
+        align: 4,
+        entsize: SYM_SIZE as u32,
+    });
+    headers.push(SectionHeader {
+        name: strtab_name,
+        kind: SHT_STRTAB,
+        flags: 0,
+        addr: 0,
+        offset: strtab_offset as u32,
+        size: strtab.bytes.len() as u32,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+    headers.push(SectionHeader {
+        name: shstrtab_name,
+        kind: SHT_STRTAB,
+        flags: 0,
+        addr: 0,
+        offset: shstrtab_offset as u32,
+        size: shstrtab.bytes.len() as u32,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+
+    let mut out = Vec::with_capacity(shoff + headers.len() * SHDR_SIZE);
+
+    // ELF32, big-endian, version 1, System V ABI
+    out.extend_from_slice(b"\x7fELF");
+    out.extend_from_slice(&[1, 2, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&ET_EXEC.to_be_bytes());
+    out.extend_from_slice(&EM_PPC.to_be_bytes());
+    out.extend_from_slice(&1u32.to_be_bytes()); // version
+    out.extend_from_slice(&0u32.to_be_bytes()); // entry
+    out.extend_from_slice(&0u32.to_be_bytes()); // program headers
+    out.extend_from_slice(&(shoff as u32).to_be_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes()); // flags
+    out.extend_from_slice(&(EHDR_SIZE as u16).to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes()); // program header size
+    out.extend_from_slice(&0u16.to_be_bytes()); // program header count
+    out.extend_from_slice(&(SHDR_SIZE as u16).to_be_bytes());
+    out.extend_from_slice(&(headers.len() as u16).to_be_bytes());
+    out.extend_from_slice(&(headers.len() as u16 - 1).to_be_bytes()); // .shstrtab is last
+
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab.bytes);
+    out.extend_from_slice(&shstrtab.bytes);
+    out.resize(shoff, 0);
+
+    for header in headers.iter() {
+        let fields = [
+            header.name, header.kind, header.flags, header.addr, header.offset,
+            header.size, header.link, header.info, header.align, header.entsize,
+        ];
+        for field in fields {
+            out.extend_from_slice(&field.to_be_bytes());
+        }
+    }
+
+    out
+}
+
+/// A string table, starting with the empty string.
+struct StringTable {
+    bytes: Vec<u8>,
+}
+
+impl StringTable {
+    fn new() -> StringTable {
+        StringTable { bytes: vec![0] }
+    }
+
+    /// Appends `s`, returning its offset.
+    fn add(&mut self, s: &str) -> u32 {
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80003130 0000c4 80003130 0 memset_internal
+
+.data section layout
+
+.sdata section layout
+804d36a0 000004 804d36a0 0 some_global
+";
+
+    fn u16_at(elf: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes(elf[offset..offset+2].try_into().unwrap())
+    }
+
+    fn u32_at(elf: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(elf[offset..offset+4].try_into().unwrap())
+    }
+
+    fn str_at(elf: &[u8], offset: usize) -> &str {
+        let len = elf[offset..].iter().position(|&b| b == 0).unwrap();
+        std::str::from_utf8(&elf[offset..offset+len]).unwrap()
+    }
+
+    /// Section headers as (name, type, addr, offset, size, link).
+    fn sections(elf: &[u8]) -> Vec<(&str, u32, u32, usize, usize, u32)> {
+        let shoff = u32_at(elf, 32) as usize;
+        let count = u16_at(elf, 48) as usize;
+        let shstrndx = u16_at(elf, 50) as usize;
+        let header = |i: usize| shoff + i * SHDR_SIZE;
+        let shstrtab = u32_at(elf, header(shstrndx) + 16) as usize;
+
+        (0..count).map(|i| {
+            let h = header(i);
+            (
+                str_at(elf, shstrtab + u32_at(elf, h) as usize),
+                u32_at(elf, h + 4),
+                u32_at(elf, h + 12),
+                u32_at(elf, h + 16) as usize,
+                u32_at(elf, h + 20) as usize,
+                u32_at(elf, h + 24),
+            )
+        }).collect()
+    }
+
+    /// Symbols as (name, value, size, info, shndx).
+    fn symbols(elf: &[u8]) -> Vec<(&str, u32, u32, u8, u16)> {
+        let sections = sections(elf);
+        let &(_, _, _, offset, size, link) = sections.iter().find(|s| s.1 == SHT_SYMTAB).unwrap();
+        let strtab = sections[link as usize].3;
+
+        (offset..offset+size).step_by(SYM_SIZE).skip(1).map(|s| (
+            str_at(elf, strtab + u32_at(elf, s) as usize),
+            u32_at(elf, s + 4),
+            u32_at(elf, s + 8),
+            elf[s + 12],
+            u16_at(elf, s + 14),
+        )).collect()
+    }
+
+    #[test]
+    fn header() {
+        let elf = write_elf(&MapFile::parse(MAP).unwrap(), false);
+        assert_eq!(&elf[..6], b"\x7fELF\x01\x02");
+        assert_eq!(u16_at(&elf, 16), ET_EXEC);
+        assert_eq!(u16_at(&elf, 18), EM_PPC);
+        assert_eq!(u32_at(&elf, 32) % 4, 0);
+        assert_eq!(elf.len(), u32_at(&elf, 32) as usize + u16_at(&elf, 48) as usize * SHDR_SIZE);
+    }
+
+    #[test]
+    fn sections_span_entries() {
+        let elf = write_elf(&MapFile::parse(MAP).unwrap(), false);
+        let sections = sections(&elf).into_iter().map(|(name, kind, addr, _, size, _)| (name, kind, addr, size)).collect::<Vec<_>>();
+        assert_eq!(sections, [
+            ("", 0, 0, 0),
+            (".text", SHT_NOBITS, 0x80003100, 0xf4),
+            (".sdata", SHT_NOBITS, 0x804d36a0, 4),
+            (".symtab", SHT_SYMTAB, 0, 3 * SYM_SIZE + SYM_SIZE),
+            (".strtab", SHT_STRTAB, 0, 1 + "memset\0memset_internal\0some_global\0".len()),
+            (".shstrtab", SHT_STRTAB, 0, 1 + ".text\0.sdata\0.symtab\0.strtab\0.shstrtab\0".len()),
+        ]);
+    }
+
+    #[test]
+    fn symbols_by_kind() {
+        let elf = write_elf(&MapFile::parse(MAP).unwrap(), false);
+        assert_eq!(symbols(&elf), [
+            ("memset", 0x80003100, 0x30, STB_GLOBAL << 4 | STT_FUNC, 1),
+            ("memset_internal", 0x80003130, 0xc4, STB_GLOBAL << 4 | STT_FUNC, 1),
+            ("some_global", 0x804d36a0, 4, STB_GLOBAL << 4 | STT_OBJECT, 2),
+        ]);
+
+        let elf = write_elf(&MapFile::parse(MAP).unwrap(), true);
+        assert!(symbols(&elf).iter().all(|s| s.4 == SHN_ABS));
+    }
+}
@@ -0,0 +1,389 @@
+//! Writing maps in other tools' symbol formats.
+
+use std::fmt::{self, Write};
+
+use crate::dtk::{self, Split};
+use crate::elf;
+use crate::header::{self, HeaderOptions};
+use crate::json;
+use crate::map::{self, MapEntry, MapFile, Section};
+use crate::rename::glob_match;
+use crate::table;
+
+/// Symbol file formats a map can be exported to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// decomp-toolkit's symbols.txt.
+    Dtk,
+    /// GNU ld script of absolute symbols.
+    Ld,
+    /// C header, see [`header`].
+    Header,
+    /// GNU assembler include of absolute symbols.
+    Asm,
+    /// Input for Ghidra's ImportSymbolsScript.py.
+    Ghidra,
+    /// IDAPython script.
+    Ida,
+    /// Binary Ninja Python snippet.
+    Binja,
+    /// ELF32 big-endian PowerPC symbol table, see [`elf`].
+    Elf,
+    /// JSON table of entries, see [`table`].
+    Json,
+    /// CSV table of entries, see [`table`].
+    Csv,
+    /// TSV table of entries, see [`table`].
+    Tsv,
+}
+
+/// Options of specific formats.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Options of the C header format.
+    pub header: HeaderOptions,
+    /// Make ELF symbols `SHN_ABS` rather than placing them in sections.
+    pub absolute: bool,
+    /// Object files, for dtk sections and the object column of tables.
+    pub objects: Vec<Split>,
+}
+
+/// Limits which entries are exported. The default exports everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Leave out placeholder names.
+    pub named_only: bool,
+    /// Only export names matching one of these globs, see [`glob_match`]. Empty exports all names.
+    pub globs: Vec<String>,
+}
+
+impl Format {
+    /// Every format, in the order the usage lists them.
+    pub const ALL: &[Format] = &[
+        Format::Dtk, Format::Ld, Format::Header, Format::Asm,
+        Format::Ghidra, Format::Ida, Format::Binja, Format::Elf,
+        Format::Json, Format::Csv, Format::Tsv,
+    ];
+
+    /// Formats that `symtool import` can read back.
+    pub const IMPORTABLE: &[Format] = &[Format::Dtk, Format::Json, Format::Csv, Format::Tsv];
+
+    /// The name `--format` takes, e.g. "dtk".
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Dtk => "dtk",
+            Format::Ld => "ld",
+            Format::Header => "header",
+            Format::Asm => "asm",
+            Format::Ghidra => "ghidra",
+            Format::Ida => "ida",
+            Format::Binja => "binja",
+            Format::Elf => "elf",
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+        }
+    }
+
+    /// Returns the format named `name`, see [`Format::name`].
+    pub fn parse(name: &str) -> Option<Format> {
+        Format::ALL.iter().copied().find(|f| f.name() == name)
+    }
+}
+
+/// Returns the map in `format`.
+pub fn export(mapfile: &MapFile, format: Format, options: &ExportOptions) -> Vec<u8> {
+    let mut out = String::new();
+    let res = match format {
+        Format::Dtk => write_dtk(mapfile, &options.objects, &mut out),
+        Format::Ld => write_ld(mapfile, &mut out),
+        Format::Header => header::write_header(mapfile, &options.header, &mut out),
+        Format::Asm => write_asm(mapfile, &mut out),
+        Format::Ghidra => write_ghidra(mapfile, &mut out),
+        Format::Ida => write_python(mapfile, IDA_SCRIPT, &mut out),
+        Format::Binja => write_python(mapfile, BINJA_SCRIPT, &mut out),
+        Format::Elf => return elf::write_elf(mapfile, options.absolute),
+        Format::Json => table::write_json(mapfile, &options.objects, &mut out),
+        Format::Csv => table::write_separated(mapfile, &options.objects, ',', &mut out),
+        Format::Tsv => table::write_separated(mapfile, &options.objects, '\t', &mut out),
+    };
+
+    // writing to a String can't fail
+    res.unwrap();
+    out.into_bytes()
+}
+
+impl Filter {
+    /// Whether the entry is exported.
+    pub fn matches(&self, entry: &MapEntry) -> bool {
+        if self.named_only && map::is_placeholder(&entry.name) { return false }
+        self.globs.is_empty() || self.globs.iter().any(|g| glob_match(g, &entry.name))
+    }
+
+    /// Returns a copy of the map with only the matching entries.
+    pub fn apply(&self, mapfile: &MapFile) -> MapFile {
+        let mut filtered = mapfile.clone();
+        for section in filtered.sections.iter_mut() {
+            section.entries.retain(|e| self.matches(e));
+        }
+        filtered
+    }
+}
+
+/// Writes decomp-toolkit's symbols.txt, one symbol per line:
+///
+/// ```text
+/// memset = .init:0x80003100; // type:function size:0x30
+/// ```
+///
+/// Entries in code sections are functions, all others objects. Placeholder names are
+/// written as dtk's own, `fn_80003100` for functions and `lbl_80003100` for objects.
+///
+/// Sections are dtk's, see [`dtk::section_at`] and [`dtk::code_ranges`]. Entries outside
+/// every DOL section, such as the exception vectors, are left out, as dtk has nowhere to
+/// put them.
+pub fn write_dtk(mapfile: &MapFile, splits: &[Split], out: &mut impl Write) -> fmt::Result {
+    let code_ranges = dtk::code_ranges(splits);
+    for (section, entry) in mapfile.section_entries() {
+        let Some(dtk_section) = dtk::section_at(section, entry.start, splits, &code_ranges) else { continue };
+        let name = dtk_name(section, &entry.name, entry.start);
+        let kind = if section.is_code() { "function" } else { "object" };
+
+        write!(out, "{} = {}:0x{:08X}; // type:{} size:0x{:X}", name, dtk_section, entry.start, kind, entry.size)?;
+        if entry.align != 0 {
+            write!(out, " align:{}", entry.align)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// The name dtk would give an entry: its own name, or dtk's placeholder for its address.
+pub fn dtk_name(section: &Section, name: &str, addr: u32) -> String {
+    if !map::is_placeholder(name) { return name.to_string() }
+    let prefix = if section.is_code() { "fn" } else { "lbl" };
+    format!("{}_{:08X}", prefix, addr)
+}
+
+/// Writes a GNU ld script defining each entry as an absolute symbol, for linking mods
+/// against the game:
+///
+/// ```text
+/// PROVIDE(memset = 0x80003100);
+/// ```
+pub fn write_ld(mapfile: &MapFile, out: &mut impl Write) -> fmt::Result {
+    for entry in mapfile.entries() {
+        writeln!(out, "PROVIDE({} = 0x{:08x});", entry.name, entry.start)?;
+    }
+    Ok(())
+}
+
+/// Writes a GNU assembler include for injection codes, defining each entry as an absolute
+/// symbol, followed by macros to load and call them:
+///
+/// ```text
+/// .set memset, 0x80003100
+/// ```
+pub fn write_asm(mapfile: &MapFile, out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "# Generated by symtool from the symbol map. Do not edit.")?;
+    for entry in mapfile.entries() {
+        writeln!(out, ".set {}, 0x{:08x}", entry.name, entry.start)?;
+    }
+    out.write_str(ASM_MACROS)
+}
+
+const ASM_MACROS: &str = r#"
+# load_addr r12, memset
+#     Loads the address of a symbol.
+.macro load_addr reg, sym
+    lis \reg, \sym@h
+    ori \reg, \reg, \sym@l
+.endm
+
+# load_word r3, some_global
+#     Loads the word at a symbol. \reg must not be r0.
+.macro load_word reg, sym
+    lis \reg, \sym@ha
+    lwz \reg, \sym@l(\reg)
+.endm
+
+# store_word r3, some_global, r12
+#     Stores \src to the word at a symbol, using \tmp for the address. \tmp must not be r0.
+.macro store_word src, sym, tmp=r12
+    lis \tmp, \sym@ha
+    stw \src, \sym@l(\tmp)
+.endm
+
+# call_addr memset
+#     Calls a function through ctr, using \tmp for the address.
+.macro call_addr sym, tmp=r12
+    lis \tmp, \sym@h
+    ori \tmp, \tmp, \sym@l
+    mtctr \tmp
+    bctrl
+.endm
+"#;
+
+/// Writes input for Ghidra's ImportSymbolsScript.py, one `name address type` line per entry,
+/// where type is `f` for functions and `l` for labels:
+///
+/// ```text
+/// memset 0x80003100 f
+/// ```
+pub fn write_ghidra(mapfile: &MapFile, out: &mut impl Write) -> fmt::Result {
+    for (section, entry) in mapfile.section_entries() {
+        let kind = if section.is_code() { "f" } else { "l" };
+        writeln!(out, "{} 0x{:08x} {}", entry.name, entry.start, kind)?;
+    }
+    Ok(())
+}
+
+/// Writes a Python script that defines `FUNCTIONS` and `DATA` as lists of
+/// `(addr, size, name)` tuples, followed by `script` to apply them.
+fn write_python(mapfile: &MapFile, script: &str, out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "# Generated by symtool from the symbol map. Do not edit.")?;
+
+    for (list, code) in [("FUNCTIONS", true), ("DATA", false)] {
+        writeln!(out, "\n{} = [", list)?;
+        for (section, entry) in mapfile.section_entries() {
+            if section.is_code() != code { continue }
+            write!(out, "    (0x{:08x}, 0x{:x}, ", entry.start, entry.size)?;
+            json::write_str(out, &entry.name)?;
+            writeln!(out, "),")?;
+        }
+        writeln!(out, "]")?;
+    }
+
+    out.write_str(script)
+}
+
+const IDA_SCRIPT: &str = r#"
+import ida_bytes
+import ida_funcs
+import ida_name
+
+for addr, size, name in FUNCTIONS:
+    func = ida_funcs.get_func(addr)
+    if func is None or func.start_ea != addr:
+        ida_bytes.del_items(addr, ida_bytes.DELIT_SIMPLE, size)
+        ida_funcs.add_func(addr, addr + size)
+    else:
+        ida_funcs.set_func_end(addr, addr + size)
+    ida_name.set_name(addr, name, ida_name.SN_NOWARN | ida_name.SN_FORCE)
+
+for addr, size, name in DATA:
+    ida_name.set_name(addr, name, ida_name.SN_NOWARN | ida_name.SN_FORCE)
+
+print("symtool: named %d functions and %d data symbols" % (len(FUNCTIONS), len(DATA)))
+"#;
+
+// run from the Python console, where `bv` is the open binary view
+const BINJA_SCRIPT: &str = r#"
+from binaryninja import Symbol, SymbolType
+
+bv.begin_undo_actions()
+for addr, size, name in FUNCTIONS:
+    if bv.get_function_at(addr) is None:
+        bv.add_function(addr)
+    bv.define_user_symbol(Symbol(SymbolType.FunctionSymbol, addr, name))
+
+for addr, size, name in DATA:
+    bv.define_user_symbol(Symbol(SymbolType.DataSymbol, addr, name))
+bv.commit_undo_actions()
+
+print("symtool: named %d functions and %d data symbols" % (len(FUNCTIONS), len(DATA)))
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+.text section layout
+80000100 000098 80000100 0 system_reset_exception_handler
+80003100 000030 80003100 0 memset
+80005340 00001c 80005340 0 zz_80005340_
+80005b64 00004c 80005b64 4 zz_80005b64_
+
+.data section layout
+803b7240 000008 803b7240 0 zz_803b7240_
+";
+
+    fn export_str(format: Format, options: &ExportOptions) -> String {
+        String::from_utf8(export(&MapFile::parse(MAP).unwrap(), format, options)).unwrap()
+    }
+
+    #[test]
+    fn dtk_sections() {
+        assert_eq!(export_str(Format::Dtk, &ExportOptions::default()), "\
+memset = .init:0x80003100; // type:function size:0x30
+fn_80005340 = .init:0x80005340; // type:function size:0x1C
+fn_80005B64 = .text:0x80005B64; // type:function size:0x4C align:4
+lbl_803B7240 = .data:0x803B7240; // type:object size:0x8
+");
+    }
+
+    #[test]
+    fn dtk_sections_from_splits() {
+        let split = Split { object: "main.c".into(), section: ".text".into(), start: 0x80005340, end: 0x80005360 };
+        let options = ExportOptions { objects: vec![split], ..ExportOptions::default() };
+        let out = export_str(Format::Dtk, &options);
+        assert!(out.contains("fn_80005340 = .text:0x80005340;"));
+        assert!(out.contains("= .text:0x80005B64;"));
+
+        // .text starts at its first split, and without .init splits there is no .init
+        assert!(!out.contains(":0x80003100;"));
+    }
+
+    #[test]
+    fn formats_by_name() {
+        for &format in Format::ALL {
+            assert_eq!(Format::parse(format.name()), Some(format));
+        }
+        assert_eq!(Format::parse("symbols"), None);
+    }
+
+    #[test]
+    fn filter() {
+        let filter = Filter { named_only: true, globs: vec!["mem*".into(), "system_*".into()] };
+        let mapfile = filter.apply(&MapFile::parse(MAP).unwrap());
+        let names = mapfile.entries().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["system_reset_exception_handler", "memset"]);
+        assert_eq!(mapfile.sections.len(), 2);
+    }
+
+    #[test]
+    fn ld() {
+        let out = export_str(Format::Ld, &ExportOptions::default());
+        assert!(out.starts_with("PROVIDE(system_reset_exception_handler = 0x80000100);\nPROVIDE(memset = 0x80003100);\n"));
+        assert_eq!(out.lines().count(), 5);
+    }
+
+    #[test]
+    fn asm() {
+        let out = export_str(Format::Asm, &ExportOptions::default());
+        assert!(out.starts_with("# Generated by symtool from the symbol map. Do not edit.\n.set system_reset_exception_handler, 0x80000100\n"));
+        assert!(out.contains(".set memset, 0x80003100\n"));
+        assert!(out.ends_with(ASM_MACROS));
+        for name in ["load_addr", "load_word", "store_word", "call_addr"] {
+            assert!(ASM_MACROS.contains(&format!(".macro {} ", name)));
+        }
+    }
+
+    #[test]
+    fn ghidra() {
+        let out = export_str(Format::Ghidra, &ExportOptions::default());
+        assert!(out.starts_with("system_reset_exception_handler 0x80000100 f\nmemset 0x80003100 f\n"));
+        assert!(out.ends_with("zz_803b7240_ 0x803b7240 l\n"));
+    }
+
+    #[test]
+    fn python() {
+        for (format, script) in [(Format::Ida, IDA_SCRIPT), (Format::Binja, BINJA_SCRIPT)] {
+            let out = export_str(format, &ExportOptions::default());
+            assert!(out.contains("\nFUNCTIONS = [\n    (0x80000100, 0x98, \"system_reset_exception_handler\"),\n"));
+            assert!(out.contains("\nDATA = [\n    (0x803b7240, 0x8, \"zz_803b7240_\"),\n]\n"));
+            assert!(out.ends_with(script));
+        }
+    }
+}
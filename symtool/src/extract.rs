@@ -0,0 +1,327 @@
+//! Finding function names in C source.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::CharIndices;
+
+/// Extensions of C and C++ source files.
+pub const SOURCE_EXTENSIONS: &[&str] = &["c", "h", "cc"];
+
+/// Extensions of C and C++ header files.
+pub const HEADER_EXTENSIONS: &[&str] = &["h"];
+
+/// Iterator over the names of all functions declared, defined or called in C source.
+///
+/// This is a heuristic scan, not a C parser. Any identifier followed by `(` is
+/// a function name, except keywords and function pointer declarations.
+pub struct FnNames<'a> {
+    src: CharIndices<'a>,
+}
+
+/// Returns the names of all functions in C source, in order of appearance, see [`FnNames`].
+pub fn fn_names(src: &str) -> FnNames<'_> {
+    FnNames { src: src.char_indices() }
+}
+
+impl<'a> Iterator for FnNames<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let src_iter = &mut self.src;
+        
+        while !src_iter.as_str().is_empty() {
+            let found = 'find_fn: {
+                take_whitespace(src_iter);
+                
+                // take function name
+                let fn_name = take_c_token(src_iter);
+                if fn_name.is_empty() { break 'find_fn None; }
+                
+                // ensure function call
+                take_whitespace(src_iter);
+                if take_while(src_iter, |c| c == '(').is_empty() { break 'find_fn None; }
+                
+                // filter function pointers/typedefs
+                take_whitespace(src_iter);
+                if !take_while(src_iter, |c| c == '*').is_empty() { break 'find_fn None; }
+                
+                // filter builtins
+                match fn_name {
+                    "if" | "for" | "while" | "return" | "switch" | "case"
+                        | "sizeof" | "alignof" | "__attribute__" => break 'find_fn None,
+                    _ => {},
+                }
+                
+                Some(fn_name)
+            };
+            
+            // skip until next symbol, then try again
+            take_while(src_iter, |c| !c.is_ascii_alphabetic() && c != '_');
+            
+            if found.is_some() { return found }
+        }
+        
+        None
+    }
+}
+
+/// A function prototype, e.g. `void HSD_JObjSetMtxDirty(HSD_JObj *jobj)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Prototype {
+    /// Return type, e.g. `void *`.
+    pub ret: String,
+    /// Function name.
+    pub name: String,
+    /// Parameter list without the parentheses.
+    pub params: String,
+}
+
+/// Words that may precede a function's return type without being part of it.
+const FUNCTION_SPECIFIERS: &[&str] = &["extern", "inline", "__inline", "__inline__", "__forceinline"];
+
+impl Prototype {
+    /// Parses a single declaration, with or without the trailing `;`.
+    /// Function pointers, typedefs and `static` functions are not prototypes.
+    pub fn parse(decl: &str) -> Option<Prototype> {
+        let decl = decl.split_whitespace().collect::<Vec<_>>().join(" ");
+        let decl = decl.trim_end_matches(';').trim_end();
+        let decl = decl.strip_prefix("extern ").unwrap_or(decl);
+        if !decl.ends_with(')') || decl.starts_with("typedef ") { return None }
+
+        // find the '(' matching the final ')'
+        let mut depth = 0;
+        let open = decl.char_indices().rev().find_map(|(i, c)| {
+            match c {
+                ')' => depth += 1,
+                '(' => { depth -= 1; if depth == 0 { return Some(i) } }
+                _ => {}
+            }
+            None
+        })?;
+
+        let before = decl[..open].trim_end();
+        let name_len = before.bytes().rev().take_while(|&b| b.is_ascii_alphanumeric() || b == b'_').count();
+        let name = &before[before.len() - name_len..];
+        let ret = before[..before.len() - name_len].trim_end();
+        if ret.contains(['(', ')', '=', '#']) || ret.split(' ').any(|w| w == "static") { return None }
+
+        // function specifiers are not part of the return type
+        let ret = ret.split(' ').filter(|w| !FUNCTION_SPECIFIERS.contains(w)).collect::<Vec<_>>().join(" ");
+        if !crate::map::is_identifier(name) || ret.is_empty() { return None }
+        if matches!(name, "if" | "for" | "while" | "return" | "switch" | "sizeof") { return None }
+
+        Some(Prototype { ret, name: name.to_string(), params: decl[open+1..decl.len()-1].to_string() })
+    }
+}
+
+/// Writes the prototype as a declaration, e.g. `void HSD_JObjSetMtxDirty(HSD_JObj *jobj);`.
+impl fmt::Display for Prototype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}({});", self.ret, self.name, self.params)
+    }
+}
+
+/// Returns the prototypes of all functions declared or defined at the top level of C source.
+///
+/// Like [`fn_names`], this is a heuristic scan rather than a C parser.
+pub fn fn_prototypes(src: &str) -> Vec<Prototype> {
+    let code = strip_comments(src);
+    let mut prototypes = Vec::new();
+
+    // whether each open brace counts towards the nesting depth, `extern "C" {` doesn't
+    let mut braces = Vec::<bool>::new();
+    let mut depth = 0;
+    let mut stmt_start = 0;
+
+    for (i, c) in code.char_indices() {
+        match c {
+            '{' => {
+                let stmt = code[stmt_start..i].trim();
+                let counts = stmt != "extern \"C\"";
+                if depth == 0 && counts {
+                    prototypes.extend(Prototype::parse(stmt));
+                }
+                braces.push(counts);
+                if counts { depth += 1; }
+                stmt_start = i + 1;
+            }
+            '}' => {
+                if braces.pop() == Some(true) { depth -= 1; }
+                stmt_start = i + 1;
+            }
+            ';' if depth == 0 => {
+                prototypes.extend(Prototype::parse(&code[stmt_start..i]));
+                stmt_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    prototypes
+}
+
+/// Replaces comments and preprocessor lines with spaces.
+fn strip_comments(src: &str) -> String {
+    let mut code = String::with_capacity(src.len());
+    let mut rest = src;
+    let mut line_start = true;
+
+    while let Some(c) = rest.chars().next() {
+        let skip = if rest.starts_with("//") || (line_start && c == '#') {
+            // preprocessor lines may continue with '\'
+            let mut end = 0;
+            for line in rest.split_inclusive('\n') {
+                end += line.len();
+                if !line.trim_end().ends_with('\\') { break }
+            }
+            end
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            comment.find("*/").map_or(rest.len(), |end| end + 4)
+        } else {
+            0
+        };
+
+        if skip != 0 {
+            code.push(' ');
+            rest = &rest[skip..];
+            line_start = true;
+            continue;
+        }
+
+        if c == '\n' {
+            line_start = true;
+        } else if !c.is_ascii_whitespace() {
+            line_start = false;
+        }
+        code.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    code
+}
+
+/// Consumes characters while `f` holds and returns them.
+pub(crate) fn take_while<'a>(src: &mut CharIndices<'a>, f: fn(char) -> bool) -> &'a str {
+    let start_i = src.offset();
+    let rest = src.as_str();
+
+    loop {
+        match src.as_str().chars().next() {
+            Some(c) if f(c) => src.next(),
+            _ => break,
+        };
+    }
+
+    let end_i = src.offset();
+    &rest[..(end_i - start_i)]
+}
+
+pub(crate) fn take_whitespace<'a>(src: &mut CharIndices<'a>) -> &'a str {
+    take_while(src, |c| c.is_ascii_whitespace())
+}
+
+/// Consumes a C identifier, returning an empty string if there is none.
+pub(crate) fn take_c_token<'a>(src: &mut CharIndices<'a>) -> &'a str {
+    let start_i = src.offset();
+    let rest = src.as_str();
+    
+    'check_token: {
+        // initial character check to prevent starting with number
+        match src.as_str().chars().next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => src.next(),
+            _ => break 'check_token,
+        };
+
+        // allow numbers in proceeding characters
+        loop {
+            match src.as_str().chars().next() {
+                Some(c) if c.is_ascii_alphanumeric() || c == '_' => src.next(),
+                _ => break 'check_token,
+            };
+        }
+    }
+
+    let end_i = src.offset();
+    &rest[..(end_i - start_i)]
+}
+
+/// Recursively lists all files under `root_path`, or `root_path` itself if it is a file.
+///
+/// Directories that cannot be read are passed to `on_err` and skipped.
+pub fn files_in_path(root_path: &Path, mut on_err: impl FnMut(&Path, std::io::Error)) -> Vec<PathBuf> {
+    if root_path.is_file() {
+        return vec![root_path.to_owned()];
+    }
+    
+    let mut files = Vec::new();
+    let mut dir_stack = Vec::new();
+    dir_stack.push(root_path.to_owned());
+    
+    while let Some(path) = dir_stack.pop() {
+        let iter = match std::fs::read_dir(&path) {
+            Ok(iter) => iter,
+            Err(e) => {
+                on_err(&path, e);
+                continue
+            }
+        };
+
+        for entry in iter {
+            let Ok(entry) = entry else { continue };
+            let Ok(metadata) = entry.metadata() else { continue };
+            
+            let name = entry.file_name();
+            let new_path = path.join(name);
+            
+            let file_type = metadata.file_type();
+            if file_type.is_dir() {
+                dir_stack.push(new_path);
+            } else if file_type.is_file() {
+                files.push(new_path);
+            }
+        }
+    }
+    
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proto(decl: &str) -> Option<String> {
+        Prototype::parse(decl).map(|p| p.to_string())
+    }
+
+    #[test]
+    fn prototypes() {
+        assert_eq!(proto("void HSD_JObjSetMtxDirty(HSD_JObj *jobj)"), Some("void HSD_JObjSetMtxDirty(HSD_JObj *jobj);".into()));
+        assert_eq!(proto("extern void *memset(void *dst,\n    int c, u32 n);"), Some("void * memset(void *dst, int c, u32 n);".into()));
+        assert_eq!(proto("inline void foo(void);"), Some("void foo(void);".into()));
+        assert_eq!(proto("__inline s32 bar(void)"), Some("s32 bar(void);".into()));
+        assert_eq!(proto("inline foo(void)"), None);
+        assert_eq!(proto("static void foo(void)"), None);
+        assert_eq!(proto("static inline void foo(void)"), None);
+        assert_eq!(proto("typedef void fn(void)"), None);
+        assert_eq!(proto("void (*callback)(void)"), None);
+        assert_eq!(proto("x = f(y)"), None);
+    }
+
+    #[test]
+    fn prototypes_in_source() {
+        let src = "\
+#include <dolphin.h>
+// void commented(void);
+void declared(int x);
+extern \"C\" {
+s32 defined(void) {
+    if (x) { return call(x); }
+    return 0;
+}
+}
+static void hidden(void) {}
+";
+        let names = fn_prototypes(src).into_iter().map(|p| p.name).collect::<Vec<_>>();
+        assert_eq!(names, ["declared", "defined"]);
+    }
+}
@@ -0,0 +1,128 @@
+//! C headers declaring the game's functions at their addresses.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+
+use crate::extract::Prototype;
+use crate::map::{MapFile, Section};
+use crate::stats::name_prefix;
+
+// C headers for mods that call game functions without a linker script:
+//
+//     #define ADDR_memset 0x80003100
+//     #define memset ((void *(*)(void *dst, int c, u32 n))0x80003100)
+//
+// or, for mods that link against an `export --format ld` script:
+//
+//     #define ADDR_memset 0x80003100
+//     void *memset(void *dst, int c, u32 n);
+
+/// Options of [`write_header`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeaderOptions {
+    /// Declare functions instead of defining function pointer macros.
+    pub externs: bool,
+    /// Known prototypes by function name. Other functions are declared as `void fn()`.
+    pub prototypes: HashMap<String, Prototype>,
+}
+
+/// Writes a header with an address constant for every entry, and a function pointer
+/// macro or declaration for every entry in a code section.
+pub fn write_header(mapfile: &MapFile, options: &HeaderOptions, out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "// Generated by symtool from the symbol map. Do not edit.")?;
+    writeln!(out, "#pragma once")?;
+
+    for section in mapfile.sections.iter() {
+        if section.entries.is_empty() { continue }
+        writeln!(out, "\n// {}", section.name)?;
+
+        for entry in section.entries.iter() {
+            writeln!(out, "#define ADDR_{} 0x{:08x}", entry.name, entry.start)?;
+            if !section.is_code() { continue }
+
+            let (ret, params) = match options.prototypes.get(&entry.name) {
+                Some(p) => (p.ret.as_str(), p.params.as_str()),
+                None => ("void", ""),
+            };
+            if options.externs {
+                writeln!(out, "{} {}({});", ret, entry.name, params)?;
+            } else {
+                writeln!(out, "#define {} (({} (*)({}))0x{:08x})", entry.name, ret, params, entry.start)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits a map by name prefix (see [`name_prefix`]), for one header per prefix.
+/// Returns the file name stem of each header, e.g. "HSD" for names starting with "HSD_",
+/// and "other" for names without a prefix.
+pub fn split_by_prefix(mapfile: &MapFile) -> BTreeMap<String, MapFile> {
+    let mut headers = BTreeMap::<String, MapFile>::new();
+
+    for (section, entry) in mapfile.section_entries() {
+        let stem = name_prefix(&entry.name).map_or("other", |p| p.trim_end_matches('_'));
+        let header = headers.entry(stem.to_string()).or_insert_with(|| MapFile { sections: Vec::new() });
+
+        if header.section(&section.name).is_none() {
+            header.sections.push(Section { name: section.name.clone(), entries: Vec::new() });
+        }
+        header.section_mut(&section.name).unwrap().entries.push(entry.clone());
+    }
+
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80005340 00001c 80005340 0 HSD_JObjSetMtxDirty
+
+.data section layout
+803b7240 000008 803b7240 0 HSD_table
+";
+
+    fn header(options: &HeaderOptions) -> String {
+        let mut out = String::new();
+        write_header(&MapFile::parse(MAP).unwrap(), options, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn macros() {
+        let prototype = Prototype::parse("inline void *memset(void *dst, int c, u32 n)").unwrap();
+        let options = HeaderOptions { externs: false, prototypes: HashMap::from([("memset".to_string(), prototype)]) };
+        assert_eq!(header(&options), "\
+// Generated by symtool from the symbol map. Do not edit.
+#pragma once
+
+// .text
+#define ADDR_memset 0x80003100
+#define memset ((void * (*)(void *dst, int c, u32 n))0x80003100)
+#define ADDR_HSD_JObjSetMtxDirty 0x80005340
+#define HSD_JObjSetMtxDirty ((void (*)())0x80005340)
+
+// .data
+#define ADDR_HSD_table 0x803b7240
+");
+    }
+
+    #[test]
+    fn externs() {
+        let out = header(&HeaderOptions { externs: true, ..HeaderOptions::default() });
+        assert!(out.contains("#define ADDR_memset 0x80003100\nvoid memset();\n"));
+    }
+
+    #[test]
+    fn split() {
+        let parts = split_by_prefix(&MapFile::parse(MAP).unwrap());
+        assert_eq!(parts.keys().collect::<Vec<_>>(), ["HSD", "other"]);
+        assert_eq!(parts["HSD"].entries().count(), 2);
+        assert_eq!(parts["HSD"].sections.len(), 2);
+    }
+}
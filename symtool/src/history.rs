@@ -0,0 +1,230 @@
+//! Who named each entry, when and why.
+
+use std::fmt;
+
+use crate::journal::Change;
+use crate::map::{MapFile, ParseError, ParseErrorKind};
+
+// The history records who gave each entry its name, when, and from what. One tab separated
+// line per name change, appended by every `update` and `rename`:
+//
+//     1729000000	.text	80003100	zz_80003100_	memset	Jane Doe	matched against the SDK
+//
+// The old name of an inserted entry is '-', as is the new name of a removed one, e.g. by
+// `symtool undo`. The note is optional and may be empty.
+
+/// One name change of one entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NameChange {
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// Section of the entry.
+    pub section: String,
+    /// Start address of the entry.
+    pub addr: u32,
+    /// `None` if the entry was inserted with this name.
+    pub old: Option<String>,
+    /// `None` if the entry was removed.
+    pub new: Option<String>,
+    /// Who made the change, see `symtool history`.
+    pub author: String,
+    /// Free-form note on where the name came from.
+    pub note: String,
+}
+
+/// Returns the name changes among the changes of a journal batch.
+pub fn name_changes(changes: &[Change], timestamp: u64, author: &str, note: &str) -> Vec<NameChange> {
+    let mut history = Vec::new();
+
+    for change in changes {
+        let old = change.old.as_ref().map(|e| e.name.clone());
+        let new = change.new.as_ref().map(|e| e.name.clone());
+        if old == new { continue }
+
+        let Some(entry) = change.new.as_ref().or(change.old.as_ref()) else { continue };
+        history.push(NameChange {
+            timestamp,
+            section: change.section.clone(),
+            addr: entry.start,
+            old,
+            new,
+            author: sanitize(author),
+            note: sanitize(note),
+        });
+    }
+
+    history
+}
+
+/// Parses a history file, oldest first.
+pub fn parse_history(src: &str) -> Result<Vec<NameChange>, ParseError> {
+    let mut history = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let err = |kind| ParseError { line: i + 1, kind };
+        if line.trim().is_empty() { continue }
+
+        let mut columns = line.split('\t');
+        let mut column = |name| columns.next().ok_or(err(ParseErrorKind::MissingColumn(name)));
+
+        let timestamp = column("timestamp")?.parse().map_err(|_| err(ParseErrorKind::MissingColumn("timestamp")))?;
+        let section = column("section")?.to_string();
+        let addr = u32::from_str_radix(column("addr")?, 16).map_err(|_| err(ParseErrorKind::InvalidHex("addr")))?;
+        let old = match column("old")? {
+            "-" => None,
+            old => Some(old.to_string()),
+        };
+        let new = match column("new")? {
+            "-" => None,
+            new => Some(new.to_string()),
+        };
+        let author = column("author")?.to_string();
+        let note = column("note").unwrap_or("").to_string();
+
+        history.push(NameChange { timestamp, section, addr, old, new, author, note });
+    }
+
+    Ok(history)
+}
+
+/// Returns the changes of the entry at `addr`, oldest first.
+pub fn timeline(history: &[NameChange], addr: u32) -> Vec<&NameChange> {
+    history.iter().filter(|c| c.addr == addr).collect()
+}
+
+/// Returns the addresses that have ever been named `name`, in order of first use.
+pub fn name_addrs(history: &[NameChange], name: &str) -> Vec<u32> {
+    let mut addrs = Vec::new();
+    for change in history {
+        let matches = change.new.as_deref() == Some(name) || change.old.as_deref() == Some(name);
+        if matches && !addrs.contains(&change.addr) {
+            addrs.push(change.addr);
+        }
+    }
+    addrs
+}
+
+/// Returns the addresses a `history` query refers to: those ever named `query`, or now
+/// holding it in `mapfile`. Only if there are none, an 8 digit hex query such as `80003100`
+/// is taken as an address, as names like `deadbeef` are valid too. `0x` is always an address.
+pub fn query_addrs(history: &[NameChange], mapfile: &MapFile, query: &str) -> Vec<u32> {
+    let mut addrs = name_addrs(history, query);
+    if let Some((_, entry)) = mapfile.entry_by_name(query) && !addrs.contains(&entry.start) {
+        addrs.push(entry.start);
+    }
+
+    let hex = query.strip_prefix("0x");
+    if addrs.is_empty() || hex.is_some() {
+        let hex = hex.unwrap_or(query);
+        if hex.len() == 8 && hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return vec![u32::from_str_radix(hex, 16).unwrap()];
+        }
+    }
+
+    addrs
+}
+
+/// Formats a unix timestamp as a UTC date and time, e.g. "2024-10-15 13:20:00".
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let secs = timestamp % 86400;
+
+    // civil date from days since 1970-01-01, see https://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Tabs and newlines would break the line format.
+fn sanitize(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Writes the change as a line of the history file.
+impl fmt::Display for NameChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f, "{}\t{}\t{:08x}\t{}\t{}\t{}\t{}",
+            self.timestamp, self.section, self.addr, self.old.as_deref().unwrap_or("-"),
+            self.new.as_deref().unwrap_or("-"), self.author, self.note,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal;
+
+    const OLD: &str = ".text section layout\n80005340 00001c 80005340 0 zz_80005340_\n80005400 000010 80005400 0 Gone\n";
+    const NEW: &str = ".text section layout\n80005340 00001c 80005340 0 Foo\n80005380 000010 80005380 0 zz_80005380_\n";
+
+    fn changes(old: &str, new: &str) -> Vec<NameChange> {
+        let changes = journal::changes(&MapFile::parse(old).unwrap(), &MapFile::parse(new).unwrap());
+        name_changes(&changes, 1729000000, "Jane Doe", "from\tthe SDK")
+    }
+
+    #[test]
+    fn renames_inserts_and_removals() {
+        let history = changes(OLD, NEW);
+        let summary = history.iter().map(|c| (c.addr, c.old.as_deref(), c.new.as_deref())).collect::<Vec<_>>();
+        assert_eq!(summary, [
+            (0x80005340, Some("zz_80005340_"), Some("Foo")),
+            (0x80005380, None, Some("zz_80005380_")),
+            (0x80005400, Some("Gone"), None),
+        ]);
+        assert_eq!(history[0].note, "from the SDK");
+
+        // undoing swaps old and new
+        let undone = changes(NEW, OLD);
+        assert_eq!(undone[0].old.as_deref(), Some("Foo"));
+        assert_eq!(undone[1].new, None);
+    }
+
+    #[test]
+    fn history_round_trip() {
+        let history = changes(OLD, NEW);
+        let src = history.iter().map(|c| c.to_string()).collect::<String>();
+        assert!(src.starts_with("1729000000\t.text\t80005340\tzz_80005340_\tFoo\tJane Doe\tfrom the SDK\n"));
+        assert_eq!(parse_history(&src).unwrap(), history);
+    }
+
+    #[test]
+    fn lookups() {
+        // Foo moves from 80005340 to 80005380
+        let moved = ".text section layout\n80005340 00001c 80005340 0 Bar\n80005380 000010 80005380 0 Foo\n";
+        let history = [changes(OLD, NEW), changes(NEW, moved)].concat();
+        assert_eq!(timeline(&history, 0x80005340).len(), 2);
+        assert_eq!(name_addrs(&history, "Foo"), [0x80005340, 0x80005380]);
+        assert_eq!(name_addrs(&history, "Gone"), [0x80005400]);
+    }
+
+    #[test]
+    fn queries() {
+        let mapfile = MapFile::parse(".text section layout\n80005340 00001c 80005340 0 deadbeef\n").unwrap();
+        let history = changes(OLD, NEW);
+
+        // hex digits are a name first, an address only if nothing is named so
+        assert_eq!(query_addrs(&history, &mapfile, "deadbeef"), [0x80005340]);
+        assert_eq!(query_addrs(&history, &mapfile, "cafebabe"), [0xcafebabe]);
+        assert_eq!(query_addrs(&history, &mapfile, "0x80005400"), [0x80005400]);
+        assert_eq!(query_addrs(&history, &mapfile, "Gone"), [0x80005400]);
+        assert_eq!(query_addrs(&history, &mapfile, "cafe"), []);
+        assert_eq!(query_addrs(&history, &mapfile, "+afebabe"), []);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(1729000000), "2024-10-15 13:46:40");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00");
+    }
+}
@@ -0,0 +1,245 @@
+//! The journal of map edits, for undo.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::map::{MapEntry, MapFile, ParseError, ParseErrorKind, Section};
+
+// The journal records every batch of edits applied to a map, so they can be undone.
+//
+//     batch 1729000000 update -f GALE01.map
+//     .text - 80003100 000030 80003100 0 zz_80003100_
+//     .text + 80003100 000030 80003100 0 memset
+//
+// '-' lines are entries as they were before the batch, '+' lines as they were after.
+// An entry that was added only has a '+' line, an entry that was removed only a '-' line.
+
+/// One batch of edits, e.g. a single `update` run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Batch {
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// The command that made the edits.
+    pub command: String,
+    /// Changed entries, in map order.
+    pub changes: Vec<Change>,
+}
+
+/// A change to the entry at one address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// Section of the entry.
+    pub section: String,
+    /// The entry before the batch, `None` if it was added.
+    pub old: Option<MapEntry>,
+    /// The entry after the batch, `None` if it was removed.
+    pub new: Option<MapEntry>,
+}
+
+/// The map no longer matches the state after a batch, so the batch cannot be undone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RevertError {
+    /// Section of the changed entry.
+    pub section: String,
+    /// Start address of the changed entry.
+    pub addr: u32,
+}
+
+impl fmt::Display for RevertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:08x} was changed after this batch", self.section, self.addr)
+    }
+}
+
+impl std::error::Error for RevertError {}
+
+/// Returns the changes between two versions of a map, matched by section and start address.
+pub fn changes(old: &MapFile, new: &MapFile) -> Vec<Change> {
+    let mut section_names = Vec::<&str>::new();
+    for section in old.sections.iter().chain(new.sections.iter()) {
+        if !section_names.contains(&section.name.as_str()) {
+            section_names.push(&section.name);
+        }
+    }
+
+    let by_addr = |section: Option<&Section>| {
+        let mut entries = BTreeMap::<u32, MapEntry>::new();
+        for entry in section.iter().flat_map(|s| s.entries.iter()) {
+            entries.insert(entry.start, entry.clone());
+        }
+        entries
+    };
+
+    let mut changes = Vec::new();
+    for name in section_names {
+        let mut old_entries = by_addr(old.section(name));
+        let mut new_entries = by_addr(new.section(name));
+
+        let mut addrs = old_entries.keys().chain(new_entries.keys()).copied().collect::<Vec<_>>();
+        addrs.sort_unstable();
+        addrs.dedup();
+
+        for addr in addrs {
+            let old = old_entries.remove(&addr);
+            let new = new_entries.remove(&addr);
+            if old != new {
+                changes.push(Change { section: name.to_string(), old, new });
+            }
+        }
+    }
+
+    changes
+}
+
+impl Batch {
+    /// Undoes this batch's changes to `mapfile`.
+    ///
+    /// Fails without changing `mapfile` if any entry differs from how the batch left it.
+    pub fn revert(&self, mapfile: &mut MapFile) -> Result<(), RevertError> {
+        let mut reverted = mapfile.clone();
+
+        for change in self.changes.iter().rev() {
+            let addr = change.new.as_ref().or(change.old.as_ref()).map_or(0, |e| e.start);
+            let err = || RevertError { section: change.section.clone(), addr };
+
+            if reverted.section(&change.section).is_none() {
+                reverted.sections.push(Section { name: change.section.clone(), entries: Vec::new() });
+            }
+            let section = reverted.section_mut(&change.section).unwrap();
+
+            let current = section.entries.iter().position(|e| e.start == addr);
+            match (current, &change.new) {
+                (Some(i), Some(new)) if section.entries[i] == *new => { section.entries.remove(i); }
+                (None, None) => {}
+                _ => return Err(err()),
+            }
+
+            if let Some(old) = &change.old {
+                section.insert_sorted(old.clone());
+            }
+        }
+
+        *mapfile = reverted;
+        Ok(())
+    }
+
+    /// Parses all batches in a journal file, oldest first.
+    pub fn parse_journal(src: &str) -> Result<Vec<Batch>, ParseError> {
+        let mut batches = Vec::<Batch>::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line_num = i + 1;
+            let err = |kind| ParseError { line: line_num, kind };
+            if line.trim().is_empty() { continue }
+
+            if let Some(rest) = line.strip_prefix("batch ") {
+                let (timestamp, command) = rest.split_once(' ').unwrap_or((rest, ""));
+                let timestamp = timestamp.parse().map_err(|_| err(ParseErrorKind::MissingColumn("timestamp")))?;
+                batches.push(Batch { timestamp, command: command.to_string(), changes: Vec::new() });
+                continue;
+            }
+
+            let Some(batch) = batches.last_mut() else {
+                return Err(err(ParseErrorKind::EntryOutsideSection));
+            };
+
+            let Some((section, rest)) = line.split_once(' ') else {
+                return Err(err(ParseErrorKind::MissingColumn("change")));
+            };
+            let (sign, entry) = rest.split_at_checked(1).ok_or(err(ParseErrorKind::MissingColumn("change")))?;
+            let entry = MapEntry::parse(entry).map_err(err)?;
+
+            // a '+' line directly after the '-' line for the same address is the same change
+            let section = section.to_string();
+            match sign {
+                "-" => batch.changes.push(Change { section, old: Some(entry), new: None }),
+                "+" => match batch.changes.last_mut() {
+                    Some(change) if change.new.is_none()
+                        && change.section == section
+                        && change.old.as_ref().is_some_and(|old| old.start == entry.start) =>
+                    {
+                        change.new = Some(entry);
+                    }
+                    _ => batch.changes.push(Change { section, old: None, new: Some(entry) }),
+                },
+                _ => return Err(err(ParseErrorKind::MissingColumn("change"))),
+            }
+        }
+
+        Ok(batches)
+    }
+}
+
+/// Writes the batch in the journal format.
+impl fmt::Display for Batch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // a line break in an argument, e.g. from --note, would end the header early
+        writeln!(f, "batch {} {}", self.timestamp, self.command.replace(|c: char| c.is_control(), " "))?;
+        for change in self.changes.iter() {
+            if let Some(old) = &change.old { writeln!(f, "{} - {}", change.section, old)?; }
+            if let Some(new) = &change.new { writeln!(f, "{} + {}", change.section, new)?; }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "\
+.text section layout
+80003100 000030 80003100 0 zz_80003100_
+80003130 0000c4 80003130 0 memset_internal
+";
+
+    const NEW: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80003200 000010 80003200 0 added
+
+.data section layout
+803b7240 000008 803b7240 0 zz_803b7240_
+";
+
+    fn batch() -> Batch {
+        let changes = changes(&MapFile::parse(OLD).unwrap(), &MapFile::parse(NEW).unwrap());
+        Batch { timestamp: 1729000000, command: "update -f GALE01.map".into(), changes }
+    }
+
+    #[test]
+    fn journal_round_trip() {
+        let batch = batch();
+        assert_eq!(batch.changes.len(), 4);
+
+        let src = batch.to_string() + &batch.to_string();
+        assert!(src.starts_with("batch 1729000000 update -f GALE01.map\n.text - 80003100 000030 80003100 0 zz_80003100_\n.text + 80003100 000030 80003100 0 memset\n"));
+        assert_eq!(Batch::parse_journal(&src).unwrap(), [batch.clone(), batch]);
+    }
+
+    #[test]
+    fn journal_command_with_line_breaks() {
+        let batch = Batch { command: "update --note 'from\nghidra\r\n\tv2' GALE01.map".into(), ..batch() };
+        let parsed = Batch::parse_journal(&batch.to_string()).unwrap();
+        assert_eq!(parsed, [Batch { command: "update --note 'from ghidra   v2' GALE01.map".into(), ..batch }]);
+    }
+
+    #[test]
+    fn revert() {
+        let mut mapfile = MapFile::parse(NEW).unwrap();
+        batch().revert(&mut mapfile).unwrap();
+
+        // the section added by the batch stays, empty
+        let expected = OLD.to_string() + "\n.data section layout\n";
+        assert_eq!(mapfile.to_string(), expected);
+    }
+
+    #[test]
+    fn revert_after_later_edit() {
+        let src = NEW.replace("0 memset\n", "0 memset2\n");
+        let mut mapfile = MapFile::parse(&src).unwrap();
+        let err = batch().revert(&mut mapfile).unwrap_err();
+        assert_eq!(err, RevertError { section: ".text".into(), addr: 0x80003100 });
+        assert_eq!(mapfile.to_string(), src);
+    }
+}
@@ -0,0 +1,307 @@
+// Minimal JSON helpers. symtool keeps its dependencies down, so no serde.
+
+use std::fmt::{self, Write};
+
+/// Writes `s` as a quoted JSON string.
+pub(crate) fn write_str(out: &mut (impl Write + ?Sized), s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// A parsed JSON value. Objects keep their keys in order.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct JsonError {
+    /// 1-based line number.
+    pub line: usize,
+    pub msg: &'static str,
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Value::Number(n) if n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&n) => Some(n as u32),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a JSON document.
+pub(crate) fn parse(src: &str) -> Result<Value, JsonError> {
+    let mut parser = Parser { src: src.as_bytes(), pos: 0 };
+    let value = parser.value()?;
+    parser.whitespace();
+    if parser.pos != parser.src.len() { return Err(parser.err("trailing characters after JSON value")) }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn err(&self, msg: &'static str) -> JsonError {
+        let line = self.src[..self.pos.min(self.src.len())].iter().filter(|&&b| b == b'\n').count() + 1;
+        JsonError { line, msg }
+    }
+
+    fn whitespace(&mut self) {
+        while self.src.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, msg: &'static str) -> Result<(), JsonError> {
+        self.whitespace();
+        if self.src.get(self.pos) != Some(&byte) { return Err(self.err(msg)) }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Result<Value, JsonError> {
+        if !self.src[self.pos..].starts_with(literal.as_bytes()) { return Err(self.err("invalid literal")) }
+        self.pos += literal.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.whitespace();
+        match self.src.get(self.pos) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.err("expected a value")),
+            None => Err(self.err("unexpected end of JSON")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, JsonError> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+
+        self.whitespace();
+        if self.src.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(fields));
+        }
+
+        loop {
+            self.whitespace();
+            if self.src.get(self.pos) != Some(&b'"') { return Err(self.err("expected a key")) }
+            let key = self.string()?;
+            self.expect(b':', "expected ':'")?;
+            fields.push((key, self.value()?));
+
+            self.whitespace();
+            match self.src.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => { self.pos += 1; return Ok(Value::Object(fields)) }
+                _ => return Err(self.err("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, JsonError> {
+        self.pos += 1;
+        let mut values = Vec::new();
+
+        self.whitespace();
+        if self.src.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.whitespace();
+            match self.src.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => { self.pos += 1; return Ok(Value::Array(values)) }
+                _ => return Err(self.err("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        while self.src.get(self.pos).is_some_and(|b| b.is_ascii_digit() || b"+-.eE".contains(b)) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+        text.parse().map(Value::Number).map_err(|_| self.err("invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut bytes = Vec::new();
+
+        loop {
+            let Some(&b) = self.src.get(self.pos) else { return Err(self.err("unterminated string")) };
+            self.pos += 1;
+
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.src.get(self.pos) else { return Err(self.err("unterminated string")) };
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.err("invalid escape")),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b if b < 0x20 => {
+                    // report the line the character is on, not the one a newline starts
+                    self.pos -= 1;
+                    return Err(self.err("control character in string"));
+                }
+                b => bytes.push(b),
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.err("invalid UTF-8 in string"))
+    }
+
+    /// Parses the digits of a `\u` escape, including a following low surrogate.
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let hex4 = |parser: &mut Self| {
+            let digits = parser.src.get(parser.pos..parser.pos+4).ok_or(parser.err("invalid \\u escape"))?;
+            let digits = std::str::from_utf8(digits).map_err(|_| parser.err("invalid \\u escape"))?;
+            let code = u32::from_str_radix(digits, 16).map_err(|_| parser.err("invalid \\u escape"))?;
+            parser.pos += 4;
+            Ok(code)
+        };
+
+        let high = hex4(self)?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.src[self.pos..].starts_with(b"\\u") { return Err(self.err("unpaired surrogate")) }
+            self.pos += 2;
+            let low = hex4(self)?;
+            if !(0xdc00..0xe000).contains(&low) { return Err(self.err("unpaired surrogate")) }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or(self.err("invalid \\u escape"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let value = parse(" {\"a\": [1, -2.5e1, true, false, null], \"b\": {}, \"c\": []} ").unwrap();
+        assert_eq!(value, Value::Object(vec![
+            ("a".into(), Value::Array(vec![
+                Value::Number(1.0), Value::Number(-25.0), Value::Bool(true), Value::Bool(false), Value::Null,
+            ])),
+            ("b".into(), Value::Object(vec![])),
+            ("c".into(), Value::Array(vec![])),
+        ]));
+        assert_eq!(value.get("a").and_then(Value::as_array).map(|a| a.len()), Some(5));
+        assert_eq!(value.get("d"), None);
+    }
+
+    #[test]
+    fn as_u32() {
+        assert_eq!(parse("4294967295").unwrap().as_u32(), Some(u32::MAX));
+        assert_eq!(parse("4294967296").unwrap().as_u32(), None);
+        assert_eq!(parse("-1").unwrap().as_u32(), None);
+        assert_eq!(parse("1.5").unwrap().as_u32(), None);
+        assert_eq!(parse("\"1\"").unwrap().as_u32(), None);
+    }
+
+    #[test]
+    fn escapes() {
+        let value = parse(r#""\"\\\/\b\f\n\r\tAé""#).unwrap();
+        assert_eq!(value.as_str(), Some("\"\\/\u{8}\u{c}\n\r\tAé"));
+
+        // characters outside the BMP are escaped as surrogate pairs
+        assert_eq!(parse(r#""😀""#).unwrap().as_str(), Some("😀"));
+        assert_eq!(parse("\"😀\"").unwrap().as_str(), Some("😀"));
+    }
+
+    #[test]
+    fn write_str_round_trip() {
+        let s = "a\"b\\c\nd\re\tf\u{1}é😀";
+        let mut out = String::new();
+        write_str(&mut out, s).unwrap();
+        assert_eq!(out, "\"a\\\"b\\\\c\\nd\\re\\tf\\u0001é😀\"");
+        assert_eq!(parse(&out).unwrap().as_str(), Some(s));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |src| parse(src).unwrap_err();
+        assert_eq!(err("{\n  \"a\": 1,\n  \"b\" 2\n}"), JsonError { line: 3, msg: "expected ':'" });
+        assert_eq!(err("[1, 2"), JsonError { line: 1, msg: "expected ',' or ']'" });
+        assert_eq!(err("{\"a\": 1} x"), JsonError { line: 1, msg: "trailing characters after JSON value" });
+        assert_eq!(err("\"abc"), JsonError { line: 1, msg: "unterminated string" });
+        assert_eq!(err("\"a\nb\""), JsonError { line: 1, msg: "control character in string" });
+        assert_eq!(err(r#""\x""#), JsonError { line: 1, msg: "invalid escape" });
+        assert_eq!(err(r#""\ud83d""#), JsonError { line: 1, msg: "unpaired surrogate" });
+        assert_eq!(err(r#""\ud83dA""#), JsonError { line: 1, msg: "unpaired surrogate" });
+        assert_eq!(err(r#""\ude00""#), JsonError { line: 1, msg: "invalid \\u escape" });
+        assert_eq!(err(r#""\u12""#), JsonError { line: 1, msg: "invalid \\u escape" });
+        assert_eq!(err("tru"), JsonError { line: 1, msg: "invalid literal" });
+        assert_eq!(err(""), JsonError { line: 1, msg: "unexpected end of JSON" });
+    }
+}
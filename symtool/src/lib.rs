@@ -0,0 +1,48 @@
+//! Library for working with the Super Smash Bros. Melee (GALE01) symbol map.
+//!
+//! - [`map`]: parsing, looking up and editing Dolphin symbol maps.
+//! - [`update`]: renaming map entries from free-form symbol and address lists.
+//! - [`rename`]: batch renaming map entries by regex.
+//! - [`refactor`]: carrying map renames over to C source.
+//! - [`lookup`]: resolving raw addresses to the symbols that contain them.
+//! - [`check`]: finding corruption such as overlapping entries or duplicate names.
+//! - [`diff`]: comparing two maps entry by entry.
+//! - [`merge`]: three-way merging of maps, e.g. as a git merge driver.
+//! - [`stats`]: naming progress reports.
+//! - [`unidiff`]: unified diffs for previewing edits.
+//! - [`journal`] and [`store`]: crash safe writes, backups and undo of map edits.
+//! - [`history`]: who named each entry, when and why.
+//! - [`export`]: writing maps in other tools' symbol formats.
+//! - [`header`]: C headers declaring the game's functions at their addresses.
+//! - [`elf`]: ELF symbol tables for standard tools.
+//! - [`table`]: JSON, CSV and TSV tables of map entries.
+//! - [`dtk`]: reading decomp-toolkit's symbols.txt and splits.txt.
+//! - [`symaddr`]: finding symbol and address pairs in free-form text.
+//! - [`extract`]: finding function names in C source.
+//!
+//! The `symtool` binary is a thin command line wrapper over this crate.
+
+#![warn(missing_docs)]
+
+pub mod map;
+pub mod lookup;
+pub mod update;
+pub mod rename;
+pub mod refactor;
+pub mod check;
+pub mod diff;
+pub mod merge;
+pub mod stats;
+pub mod unidiff;
+pub mod journal;
+pub mod store;
+pub mod history;
+pub mod export;
+pub mod header;
+pub mod elf;
+pub mod table;
+pub mod dtk;
+
+mod json;
+pub mod symaddr;
+pub mod extract;
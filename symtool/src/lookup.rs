@@ -0,0 +1,171 @@
+//! Resolving raw addresses to the symbols that contain them.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::map::{MapEntry, MapFile, Section};
+
+/// Index of map entries sorted by address, for resolving raw addresses to symbols.
+pub struct AddrIndex<'a> {
+    /// Entries of each section sorted by start address. Empty sections are dropped.
+    sections: Vec<(&'a Section, Vec<&'a MapEntry>)>,
+}
+
+/// Where an address falls in the map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Location<'a> {
+    /// The address is `offset` bytes into `entry`.
+    Symbol {
+        /// Section of the entry.
+        section: &'a Section,
+        /// The entry containing the address.
+        entry: &'a MapEntry,
+        /// Bytes from the start of the entry.
+        offset: u32,
+    },
+
+    /// The address is between two entries of the same section.
+    Gap {
+        /// Section of both entries.
+        section: &'a Section,
+        /// The entry before the address.
+        prev: &'a MapEntry,
+        /// The entry after the address.
+        next: &'a MapEntry,
+    },
+
+    /// The address is not within any section.
+    Outside,
+}
+
+impl<'a> AddrIndex<'a> {
+    /// Indexes all entries of the map.
+    pub fn new(mapfile: &'a MapFile) -> AddrIndex<'a> {
+        let mut sections = Vec::new();
+
+        for section in mapfile.sections.iter() {
+            if section.entries.is_empty() { continue }
+
+            let mut entries = section.entries.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|e| e.start);
+            sections.push((section, entries));
+        }
+
+        AddrIndex { sections }
+    }
+
+    /// Returns where `addr` falls in the map.
+    pub fn locate(&self, addr: u32) -> Location<'a> {
+        for &(section, ref entries) in self.sections.iter() {
+            // number of entries starting at or before addr
+            let i = entries.partition_point(|e| e.start <= addr);
+            if i == 0 { continue }
+
+            let prev = entries[i-1];
+            if addr - prev.start < prev.size {
+                return Location::Symbol { section, entry: prev, offset: addr - prev.start };
+            }
+
+            // past the last entry of this section
+            let Some(&next) = entries.get(i) else { continue };
+            return Location::Gap { section, prev, next };
+        }
+
+        Location::Outside
+    }
+}
+
+/// Writes the location as `lookup` prints it after the address, e.g. "memset+0x4 .text".
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Symbol { section, entry, offset: 0 } => write!(f, "{} {}", entry.name, section.name),
+            Location::Symbol { section, entry, offset } => write!(f, "{}+0x{:x} {}", entry.name, offset, section.name),
+            Location::Gap { section, prev, next } => write!(f, "gap between {} and {} {}", prev.name, next.name, section.name),
+            Location::Outside => write!(f, "outside map"),
+        }
+    }
+}
+
+/// Index of map entries by name, for resolving symbols to addresses.
+pub struct NameIndex<'a> {
+    names: HashMap<&'a str, (&'a Section, &'a MapEntry)>,
+}
+
+impl<'a> NameIndex<'a> {
+    /// Indexes all entries of the map.
+    pub fn new(mapfile: &'a MapFile) -> NameIndex<'a> {
+        let mut names = HashMap::new();
+        for (section, entry) in mapfile.section_entries() {
+            names.entry(entry.name.as_str()).or_insert((section, entry));
+        }
+        NameIndex { names }
+    }
+
+    /// Returns the first entry named `name`, like [`MapFile::entry_by_name`].
+    pub fn get(&self, name: &str) -> Option<(&'a Section, &'a MapEntry)> {
+        self.names.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80003130 0000c4 80003130 0 memset_internal
+80003400 000010 80003400 0 later
+
+.data section layout
+803b7240 000008 803b7240 0 some_table
+";
+
+    #[test]
+    fn locate() {
+        let mapfile = MapFile::parse(MAP).unwrap();
+        let index = AddrIndex::new(&mapfile);
+
+        let Location::Symbol { section, entry, offset } = index.locate(0x80003104) else { panic!() };
+        assert_eq!((section.name.as_str(), entry.name.as_str(), offset), (".text", "memset", 4));
+
+        let Location::Symbol { section, entry, offset } = index.locate(0x803b7247) else { panic!() };
+        assert_eq!((section.name.as_str(), entry.name.as_str(), offset), (".data", "some_table", 7));
+
+        let Location::Gap { prev, next, .. } = index.locate(0x80003300) else { panic!() };
+        assert_eq!((prev.name.as_str(), next.name.as_str()), ("memset_internal", "later"));
+
+        assert_eq!(index.locate(0x80000000), Location::Outside);
+        assert_eq!(index.locate(0x80003410), Location::Outside);
+        assert_eq!(index.locate(0x803b7248), Location::Outside);
+    }
+
+    #[test]
+    fn lookup_columns() {
+        let mapfile = MapFile::parse(MAP).unwrap();
+        let index = AddrIndex::new(&mapfile);
+        let line = |addr| index.locate(addr).to_string();
+
+        assert_eq!(line(0x80003100), "memset .text");
+        assert_eq!(line(0x80003104), "memset+0x4 .text");
+        assert_eq!(line(0x803b7247), "some_table+0x7 .data");
+        assert_eq!(line(0x80003300), "gap between memset_internal and later .text");
+        assert_eq!(line(0x80000000), "outside map");
+    }
+
+    #[test]
+    fn names() {
+        let src = MAP.to_string() + "803b7248 000004 803b7248 0 memset\n";
+        let mapfile = MapFile::parse(&src).unwrap();
+        let index = NameIndex::new(&mapfile);
+
+        let (section, entry) = index.get("some_table").unwrap();
+        assert_eq!((section.name.as_str(), entry.start), (".data", 0x803b7240));
+
+        // the first of duplicated names
+        let (section, entry) = index.get("memset").unwrap();
+        assert_eq!((section.name.as_str(), entry.start), (".text", 0x80003100));
+        assert!(index.get("nothing").is_none());
+    }
+}
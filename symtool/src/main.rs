@@ -1,8 +1,21 @@
+use symtool::map::{self, MapFile, ParseError};
+use symtool::{check, diff, merge, stats, store, table, unidiff};
+use symtool::export::{self, ExportOptions, Filter, Format};
+use symtool::extract::Prototype;
+use symtool::header;
+use symtool::dtk::{self, Split};
+use symtool::journal::{self, Batch};
+use symtool::history::{self, NameChange};
+use symtool::lookup::{AddrIndex, NameIndex};
+use symtool::symaddr::line_addr;
+use symtool::update::{self, UpdateOptions, UpdateRecord};
+use symtool::rename::{self, RenameRule, Scope};
+use symtool::refactor::{self, Renames};
+use symtool::extract::{self, files_in_path};
+
 use std::process::ExitCode;
-use std::path::{Path, PathBuf};
-use std::str::CharIndices;
+use std::path::Path;
 use std::collections::HashMap;
-use std::ops::Range;
 use std::io::*;
 
 const USAGE: &str = "USAGE:
@@ -10,17 +23,191 @@ const USAGE: &str = "USAGE:
         Finds and prints all function symbols in passed directory or file.
         
         -h      Only use header files
+        -p      Print the prototypes of declared and defined functions instead, e.g. for 'export --format header'
         
     symtool addr <mapfile>
-        For each piped line, find the address of that symbol given in the passed mapfile, then print the symbol and the address.
+        For each piped line, find the address of that symbol given in the passed mapfile, then print the symbol, the address and the section.
+
+        The mapfile must be a Dolphin symbol map (e.g. GALE01.map).
+        
+    symtool lookup <mapfile>
+        For each piped line, find the address on that line, then print the address and the symbol containing it.
 
-        The mapfile format is flexible. The only requirement is that the symbol and the address are on the same line.
+        Addresses inside a symbol are printed as 'symbol+0xoffset', followed by the section.
+        Addresses between two symbols or outside of all sections are reported as such.
+        If object files were imported from a splits.txt, the object file containing the address is printed last.
         
-    symtool update <mapfile>
+    symtool update [args] <mapfile>
         For each piped line, find the symbol and address on that line update the passed mapfile with the symbol.
 
-        The input and output map files formats are flexible.
-        The only requirement is that the symbol and the address are on the same line.
+        The mapfile must be a Dolphin symbol map (e.g. GALE01.map).
+        The input format is flexible. The only requirement is that the symbol and the address are on the same line.
+        If a section name (e.g. '.data') is on the line, the entry must be in that section or the update is skipped.
+
+        Only placeholder names (zz_, fn_, FUN_, sub_, lbl_ followed by the address) are replaced,
+        and incoming placeholder names are ignored. Skipped updates are listed after the renames.
+
+        A name is never given to two addresses. If an incoming name is already used at another address,
+        or is given to several addresses in the input, the update is skipped.
+
+        -f, --force     Also overwrite real names and allow placeholder names
+        -m, --move      Move names that are already used to the new address, and give the old entry its placeholder name
+        -i, --insert    Insert new entries for addresses without one. The size is taken from the line
+                        (a Dolphin map entry, or 'size:0x30'), otherwise the entry containing the address
+                        is split, or the entry extends to the next one. Entries never overlap.
+                        A section named on the line that the map lacks, e.g. '.sdata', is added if the size is known.
+        -l, --layout    Also update the size, vaddr and align columns from the line ('size:0x30', 'align:4',
+                        or a Dolphin map entry). Sizes that would overlap the next entry are skipped.
+        -n, --dry-run   Print a unified diff of the changes instead of writing the mapfile
+        -c, --check     Print a unified diff of the changes without writing the mapfile, and exit with failure if there are any
+        --note <text>   Where the names came from, recorded in the history
+
+    symtool rename [args] <mapfile>
+        Renames all entries whose name matches a regex, e.g. all Dolphin placeholders to decomp-toolkit ones:
+
+            symtool rename -e '^zz_([0-9a-f]{8})_$' 'fn_$1' GALE01.map
+
+        The replacement may use capture groups ('$1', '${name}') and '{addr}' for the entry's 8 digit hex address,
+        e.g. 'x_$1_{addr}'. Group numbers end at their last digit.
+        Renames go through the same checks as update: names that would be used twice, or that are not
+        valid identifiers, are skipped. Real names and placeholders may be replaced.
+
+        -e <regex> <replacement>    A rule. Several rules are applied in order, each to the result of the previous
+        -a <start>-<end>            Only rename entries starting in this hex address range, end exclusive
+        -s <section>                Only rename entries in this section
+        -g <glob>                   Only rename entries whose name matches this glob, e.g. 'HSD_LObj*'
+        -m, --move                  Move names that are already used to the new address, and give the old entry its placeholder name
+        -n, --dry-run               Print a unified diff of the changes instead of writing the mapfile
+        -c, --check                 Print a unified diff of the changes without writing the mapfile, and exit with failure if there are any
+        --note <text>               Why the names changed, recorded in the history
+
+    symtool refactor [args] <path>
+        For each piped 'old -> new' line, as printed by update and rename, replace the identifier old with new
+        in all C source files (.c, .h, .cc) in the passed directory or file, then print the replacements per file.
+
+        Only whole identifiers are replaced. All renames are applied at once, so names can be swapped.
+
+        -j <mapfile>    Use the renames of the last update or rename of <mapfile>, from its journal, instead of piped lines
+        -s              Skip comments, strings and character literals
+        -n, --dry-run   Print the replacements without writing any files
+
+    symtool check [args] <mapfile>
+        Checks the passed mapfile for overlapping entries, gaps between functions, duplicate names,
+        mismatched vaddrs, invalid names, out of order entries and placeholders with the wrong address.
+
+        Exits with failure if any errors are found. Gaps are only warnings.
+
+        -w      Also fail on warnings
+        -q      Only print errors
+        
+    symtool diff [args] <old mapfile> <new mapfile>
+        Compares two mapfiles by address and prints renamed, resized, moved, added and removed symbols.
+        
+        -j      Print as JSON
+        
+    symtool merge [args] <base mapfile> <our mapfile> <their mapfile>
+        Three-way merges two mapfiles by address and writes the result to <our mapfile>.
+        Changes to different entries, or to different columns of the same entry, merge cleanly.
+        Conflicting entries are written between git style conflict markers.
+        
+        Exits with failure if there are conflicts, so it can be used as a git merge driver:
+        
+            # .gitattributes
+            GALE01.map merge=symtool
+            
+            # .git/config
+            [merge \"symtool\"]
+                name = symtool map merge
+                driver = symtool merge %O %A %B
+        
+        -p      Print the result instead of writing to <our mapfile>
+        
+    symtool stats [args] <mapfile>
+        Prints naming progress: named vs placeholder entries and bytes, per section,
+        per name prefix (e.g. HSD_, GX_) and per address region.
+        
+        -m          Print as markdown tables
+        -j          Print as JSON
+        -r <size>   Size of address regions in hex (default 40000)
+        
+    symtool export [args] <mapfile>
+        Writes the passed mapfile in another tool's symbol format, to stdout unless -o is given.
+
+        Formats:
+            dtk     decomp-toolkit symbols.txt, e.g. 'memset = .init:0x80003100; // type:function size:0x30'.
+                    Placeholder names become dtk's own fn_/lbl_ names. Code is split into dtk's .init and .text
+                    by the object files recorded by 'import -s' or given with -s, each code section starting at
+                    its first split. Without .init or .text splits, GALE01's are assumed: .init from 0x80003100
+                    and .text from 0x80005940. Code outside the DOL, such as the exception vectors at
+                    0x80000100, is left out.
+            ld      GNU ld script of absolute symbols for linking mods, e.g. 'PROVIDE(memset = 0x80003100);'.
+            header  C header with an ADDR_ constant for every symbol, and a function pointer macro for every function,
+                    e.g. '#define memset ((void *(*)(void *dst, int c, u32 n))0x80003100)'.
+                    Functions without a known prototype are 'void fn()'. The header doesn't include any type definitions.
+            asm     GNU assembler include for injection codes, e.g. '.set memset, 0x80003100', with the macros
+                    load_addr, load_word, store_word and call_addr, e.g. 'call_addr memset' instead of lis/ori/mtctr/bctrl.
+            ghidra  Input for Ghidra's ImportSymbolsScript.py, e.g. 'memset 0x80003100 f'. Data are 'l' labels.
+            ida     IDAPython script creating functions with their sizes and names, and naming data.
+            binja   Binary Ninja Python snippet for the scripting console, defining function and data symbols.
+            elf     ELF32 big-endian PowerPC file holding only a symbol table, with STT_FUNC and STT_OBJECT symbols
+                    placed in NOBITS sections, for gdb's add-symbol-file, nm, objdump or 'ld --just-symbols'.
+            json    Every entry with its section, start, size, vaddr, align, name, whether the name is a placeholder,
+                    and its object file if known from 'import -s'. The schema is stable and versioned:
+                    {\"schema\": 1, \"sections\": [\".text\", ...], \"entries\": [{\"section\": \".text\", \"start\": \"0x80003100\",
+                    \"size\": 48, \"vaddr\": \"0x80003100\", \"align\": 0, \"name\": \"memset\", \"placeholder\": false, \"object\": null}, ...]}
+            csv     The same columns as json, with a header row: section,start,size,vaddr,align,name,placeholder,object.
+                    Addresses are 0x hex, sizes and alignments decimal. A row with only a section is an empty section.
+            tsv     The same as csv, tab separated and unquoted.
+
+        --format <format>   The format to write
+        -o <path>           Write to <path> instead of stdout
+        --named             Leave out placeholder names
+        -g <glob>           Only export names matching <glob>, e.g. 'HSD_*'. May be given several times
+        -p <path>           header: read known prototypes from <path>, one per line, as printed by 'extract -p'
+        --extern            header: declare functions instead of defining macros, for linking with an ld script
+        --split <dir>       header: write one header per name prefix (HSD.h, GX.h, ...) to <dir> instead
+        --abs               elf: make all symbols absolute (SHN_ABS) instead of placing them in sections
+        -s <splits.txt>     dtk, json, csv, tsv: take object files from a dtk splits.txt instead of those recorded by 'import -s'
+
+    symtool import [args] <input> <mapfile>
+        Merges the names, sizes and alignments of another tool's symbol file into the passed mapfile,
+        with the same checks and options as update.
+
+        Formats:
+            dtk     decomp-toolkit symbols.txt. dtk's fn_/lbl_ names are placeholders, only their sizes are imported.
+                    dtk's .init and .text are both code, matched to the map's code by address.
+            json    A map exported with 'export --format json', possibly edited. Replaces the whole mapfile,
+            csv     so -m and -i have no effect. The placeholder and object columns are ignored, and columns
+            tsv     may be in any order. Refused if it adds check errors, uses a name twice or has unsorted
+                    entries, or replaces real names with placeholders without -f.
+
+        --format <format>   The format of <input>
+        -s <splits.txt>     Also record the object file of each address range from a dtk splits.txt, for lookup
+        -f, --force         Also overwrite real names and allow placeholder names
+        -m, --move          Move names that are already used to the new address, and give the old entry its placeholder name
+        -i, --insert        Insert new entries for addresses without one
+        -n, --dry-run       Print a unified diff of the changes instead of writing the mapfile
+        -c, --check         Print a unified diff of the changes without writing the mapfile, and exit with failure if there are any
+        --note <text>       Where the names came from, recorded in the history
+
+    symtool undo [args] <mapfile>
+        Undoes the last batch of edits made to the passed mapfile by update or rename.
+        
+        Edits are written through a temporary file, so an interrupted edit never leaves a partial mapfile.
+        Each edit keeps the previous mapfile in <mapfile>.bak and is recorded in <mapfile>.journal.
+        <mapfile>.lock stops concurrent edits from interleaving.
+        
+        -N <count>      Undo the last <count> batches (default 1)
+        -n, --dry-run   Print a unified diff of the changes instead of writing the mapfile
+        -l              List the batches in the journal
+        
+    symtool history <symbol|addr> <mapfile>
+        Prints who named the entry at the passed address, or the entries ever named the passed symbol, when and why.
+        A query like 'deadbeef' is a name if any entry is or was named so, and an address otherwise. '0x' makes it an address.
+        
+        Every name change made by update, rename, import and undo is recorded in <mapfile>.history, with the author taken from
+        $SYMTOOL_AUTHOR, git's user.name or $USER, and the --note passed to the command.
+        Unlike the journal, the history is worth committing alongside the mapfile.
 ";
 
 macro_rules! log_err {
@@ -36,7 +223,7 @@ macro_rules! log_err {
         // print error
         stdout.write_all(b"| ").unwrap();
         write!(&mut stdout, $($v)*).unwrap();
-        stdout.write(b"\n").unwrap();
+        stdout.write_all(b"\n").unwrap();
         stdout.flush().unwrap();
     }}
 }
@@ -52,10 +239,21 @@ fn main() -> ExitCode {
     match args[1].as_str() {
         "extract" => extract(&args[2..]),
         "addr" => addr(&args[2..]),
+        "lookup" => lookup(&args[2..]),
         "update" => update(&args[2..]),
+        "rename" => rename(&args[2..]),
+        "refactor" => refactor(&args[2..]),
+        "check" => check(&args[2..]),
+        "diff" => diff(&args[2..]),
+        "merge" => merge(&args[2..]),
+        "stats" => stats(&args[2..]),
+        "export" => export(&args[2..]),
+        "import" => import(&args[2..]),
+        "undo" => undo(&args[2..]),
+        "history" => history(&args[2..]),
         _ => {
             print!("{}", USAGE);
-            return ExitCode::FAILURE;
+            ExitCode::FAILURE
         }
     }
 }
@@ -69,17 +267,21 @@ fn extract(args: &[String]) -> ExitCode {
     }
     
     let (search_path, args) = args.split_last().unwrap();
-    let paths = files_in_path(Path::new(search_path));
+    let paths = files_in_path(Path::new(search_path), |path, e| {
+        log_err!("Failed to read directory {}: {}", path.display(), e);
+    });
     
     let mut header_only = false;
+    let mut prototypes = false;
     for arg in args {
         match arg.as_str() {
             "-h" => header_only = true,
+            "-p" => prototypes = true,
             arg => log_err!("Unknown argument '{}'", arg),
         }
     }
     
-    let extensions: &[&str] = if header_only { &["h"] } else { &["c", "h", "cc"] };
+    let extensions = if header_only { extract::HEADER_EXTENSIONS } else { extract::SOURCE_EXTENSIONS };
     
     for path in paths {
         let Some(ext) = path.extension() else { continue };
@@ -99,50 +301,27 @@ fn extract(args: &[String]) -> ExitCode {
             }
         };
         
-        let mut src_iter = src.char_indices();
-        let src_iter = &mut src_iter;
-        
         let mut stdout = stdout().lock();
         
-        while !src_iter.as_str().is_empty() {
-            'find_fn: {
-                take_whitespace(src_iter);
-                
-                // take function name
-                let fn_name = take_c_token(src_iter);
-                if fn_name.is_empty() { break 'find_fn; }
-                
-                // ensure function call
-                take_whitespace(src_iter);
-                if take_while(src_iter, |c| c == '(').is_empty() { break 'find_fn; }
-                
-                // filter function pointers/typedefs
-                take_whitespace(src_iter);
-                if !take_while(src_iter, |c| c == '*').is_empty() { break 'find_fn; }
-                
-                // filter builtins
-                match fn_name {
-                    "if" | "for" | "while" | "return" | "switch" | "case"
-                        | "sizeof" | "alignof" | "__attribute__" => break 'find_fn,
-                    _ => {},
-                }
-                
-                let res = stdout.write_all(fn_name.as_bytes())
-                    .and_then(|()| stdout.write_all(b"\n"));
+        let lines: Vec<String> = if prototypes {
+            extract::fn_prototypes(&src).iter().map(|p| p.to_string()).collect()
+        } else {
+            extract::fn_names(&src).map(|name| name.to_string()).collect()
+        };
+        
+        for line in lines {
+            let res = stdout.write_all(line.as_bytes())
+                .and_then(|()| stdout.write_all(b"\n"));
 
-                match res {
-                    Err(e) if e.kind() == ErrorKind::BrokenPipe => return ExitCode::SUCCESS,
-                    Err(e) => {
-                        drop(stdout);
-                        log_err!("Could not write to stdout: {}", e);
-                        return ExitCode::FAILURE;
-                    }
-                    Ok(_) => {}
+            match res {
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => return ExitCode::SUCCESS,
+                Err(e) => {
+                    drop(stdout);
+                    log_err!("Could not write to stdout: {}", e);
+                    return ExitCode::FAILURE;
                 }
+                Ok(_) => {}
             }
-            
-            // skip until next symbol, then try again
-            take_while(src_iter, |c| !c.is_ascii_alphabetic() && c != '_');
         }
     }
     
@@ -155,19 +334,14 @@ fn addr(args: &[String]) -> ExitCode {
         return ExitCode::FAILURE;
     }
     
-    let mapfile_path = Path::new(&args[0]);
-    let mapfile = match std::fs::read_to_string(mapfile_path) {
-        Ok(mapfile) => mapfile,
-        Err(e) => {
-            log_err!("Failed to read map file {}: {}", mapfile_path.display(), e);
-            return ExitCode::FAILURE;
-        }
-    };
+    let Some(mapfile) = read_mapfile(Path::new(&args[0])) else { return ExitCode::FAILURE };
     
-    let mut maplookup = HashMap::<&str, u32>::new();
-    for line in mapfile.lines() {
-        if let Some(info) = line_symaddr(line) {
-            maplookup.insert(info.symbol, info.addr);
+    // the first entry of duplicated names is used
+    let index = NameIndex::new(&mapfile);
+    for entry in mapfile.entries() {
+        let Some((_, first)) = index.get(&entry.name) else { continue };
+        if !std::ptr::eq(first, entry) {
+            log_err!("Symbol {} is defined at both {:08X} and {:08X}", entry.name, first.start, entry.start);
         }
     }
     
@@ -176,8 +350,34 @@ fn addr(args: &[String]) -> ExitCode {
     for line in stdin.lines() {
         let Ok(line) = line else { continue };
         let sym = line.trim();
-        if let Some(addr) = maplookup.get(sym) {
-            println!("{} {:08X}", sym, addr);
+        if let Some((section, entry)) = index.get(sym) {
+            println!("{} {:08X} {}", sym, entry.start, section.name);
+        }
+    }
+    
+    ExitCode::SUCCESS
+}
+
+fn lookup(args: &[String]) -> ExitCode {
+    if args.is_empty() {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let Some(mapfile) = read_mapfile(Path::new(&args[0])) else { return ExitCode::FAILURE };
+    let index = AddrIndex::new(&mapfile);
+    let Some(splits) = read_objects(Path::new(&args[0])) else { return ExitCode::FAILURE };
+    
+    let stdin = stdin().lock();
+    for line in stdin.lines() {
+        let Ok(line) = line else { continue };
+        let Some((addr, _)) = line_addr(&line) else { continue };
+        
+        print!("{:08X} {}", addr, index.locate(addr));
+        
+        match splits.iter().find(|s| s.contains(addr)) {
+            Some(split) => println!(" {}", split.object),
+            None => println!(),
         }
     }
     
@@ -190,212 +390,1007 @@ fn update(args: &[String]) -> ExitCode {
         return ExitCode::FAILURE;
     }
     
-    let mapfile_path = Path::new(&args[0]);
-    let mut mapfile = match std::fs::read_to_string(mapfile_path) {
-        Ok(mapfile) => mapfile,
-        Err(e) => {
-            log_err!("Failed to read map file {}: {}", mapfile_path.display(), e);
-            return ExitCode::FAILURE;
+    let (mapfile_path, args) = args.split_last().unwrap();
+    let mapfile_path = Path::new(mapfile_path);
+    
+    let mut options = UpdateOptions::default();
+    let mut dry_run = false;
+    let mut check_only = false;
+    let mut note = "";
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--force" => options.force = true,
+            "-m" | "--move" => options.move_duplicates = true,
+            "-i" | "--insert" => options.insert = true,
+            "-l" | "--layout" => options.layout = true,
+            "--note" => match args.next() {
+                Some(text) => note = text.as_str(),
+                None => {
+                    log_err!("Expected a note after '--note'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-n" | "--dry-run" => dry_run = true,
+            "-c" | "--check" => check_only = true,
+            arg => log_err!("Unknown argument '{}'", arg),
         }
-    };
+    }
     
-    let mut updates = HashMap::<u32, String>::new();
+    let mut records = Vec::new();
     let stdin = stdin().lock();
     for line in stdin.lines() {
         let Ok(line) = line else { continue };
 
-        if let Some(info) = line_symaddr(&line) {
-            updates.insert(info.addr, info.symbol.to_string());
+        if let Some(record) = UpdateRecord::parse_line(&line) {
+            records.push(record);
         }
     }
 
-    if updates.is_empty() { return ExitCode::SUCCESS }
+    if records.is_empty() { return ExitCode::SUCCESS }
+    
+    let Some(_lock) = lock_mapfile(mapfile_path) else { return ExitCode::FAILURE };
+    let Some(mut mapfile) = read_mapfile(mapfile_path) else { return ExitCode::FAILURE };
+    let original = mapfile.clone();
 
-    let mut i = mapfile.len();
-    while let Some((_, line)) = mapfile[..i].rsplit_once('\n') {
-        let line_start = i - line.len();
+    let report = update::apply(&mut mapfile, &records, options);
+    
+    print_update_report(mapfile_path, &original, &mapfile, &report, dry_run || check_only);
+    
+    if check_only {
+        return if mapfile == original { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+    }
+    if dry_run { return ExitCode::SUCCESS }
+    
+    if !save_mapfile(mapfile_path, &original, &mapfile, note) { return ExitCode::FAILURE }
+    
+    ExitCode::SUCCESS
+}
 
-        'check_line: {
-            let (addr, range) = match line_symaddr(line) {
-                Some(info) => (info.addr, info.symbol_range),
-                None => break 'check_line,
-            };
-            let Some(new_symbol) = updates.get(&addr) else { break 'check_line };
-            
-            let sym_range = (line_start+range.start)..(line_start+range.end);
-            println!("{} -> {}", &mapfile[sym_range.clone()], new_symbol);
-            mapfile.replace_range(sym_range, new_symbol);
+fn rename(args: &[String]) -> ExitCode {
+    if args.is_empty() {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let (mapfile_path, args) = args.split_last().unwrap();
+    let mapfile_path = Path::new(mapfile_path);
+    
+    // renaming is explicit, so names may be replaced whatever they are
+    let mut options = UpdateOptions { force: true, ..UpdateOptions::default() };
+    let mut rules = Vec::new();
+    let mut scope = Scope::default();
+    let mut dry_run = false;
+    let mut check_only = false;
+    let mut note = "";
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" => {
+                let (Some(pattern), Some(replacement)) = (args.next(), args.next()) else {
+                    log_err!("Expected a regex and a replacement after '-e'");
+                    return ExitCode::FAILURE;
+                };
+                match RenameRule::new(pattern, replacement) {
+                    Ok(rule) => rules.push(rule),
+                    Err(e) => {
+                        log_err!("Invalid regex '{}': {}", pattern, e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            "-a" => {
+                let range = args.next().and_then(|s| s.split_once('-')).and_then(|(start, end)| {
+                    let start = u32::from_str_radix(start.trim_start_matches("0x"), 16).ok()?;
+                    let end = u32::from_str_radix(end.trim_start_matches("0x"), 16).ok()?;
+                    Some(start..end)
+                });
+                let Some(range) = range else {
+                    log_err!("Expected a hex address range like 80003100-80004000 after '-a'");
+                    return ExitCode::FAILURE;
+                };
+                scope.range = Some(range);
+            }
+            "-s" => scope.section = args.next().cloned(),
+            "-g" => scope.glob = args.next().cloned(),
+            "--note" => match args.next() {
+                Some(text) => note = text.as_str(),
+                None => {
+                    log_err!("Expected a note after '--note'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-m" | "--move" => options.move_duplicates = true,
+            "-n" | "--dry-run" => dry_run = true,
+            "-c" | "--check" => check_only = true,
+            arg => log_err!("Unknown argument '{}'", arg),
         }
-        
-        i = line_start;
-        if i != 0 { i -= 1; } else { break; }
     }
     
-    if let Err(e) = std::fs::write(mapfile_path, &mapfile) {
-        log_err!("Failed to write map file {}: {}", mapfile_path.display(), e);
+    if rules.is_empty() {
+        log_err!("Expected at least one '-e <regex> <replacement>' rule");
         return ExitCode::FAILURE;
     }
     
+    let Some(_lock) = lock_mapfile(mapfile_path) else { return ExitCode::FAILURE };
+    let Some(mut mapfile) = read_mapfile(mapfile_path) else { return ExitCode::FAILURE };
+    let original = mapfile.clone();
+
+    let records = rename::records(&mapfile, &rules, &scope);
+    let report = update::apply(&mut mapfile, &records, options);
+    
+    if dry_run || check_only {
+        print!("{}", map_diff(mapfile_path, &original, &mapfile));
+    } else {
+        for rename in report.renamed.iter() {
+            println!("{} -> {} {}", rename.old, rename.new, rename.section);
+        }
+    }
+    
+    if !report.skipped.is_empty() {
+        println!("skipped {} conflicting renames:", report.skipped.len());
+        for skipped in report.skipped.iter() {
+            println!("    {}", skipped);
+        }
+    }
+    
+    if check_only {
+        return if mapfile == original { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+    }
+    if dry_run { return ExitCode::SUCCESS }
+    
+    if !save_mapfile(mapfile_path, &original, &mapfile, note) { return ExitCode::FAILURE }
+    
     ExitCode::SUCCESS
 }
 
-// Helper functions --------------------------------------------------------
+fn refactor(args: &[String]) -> ExitCode {
+    if args.is_empty() {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let (search_path, args) = args.split_last().unwrap();
+    
+    let mut journal_mapfile = None;
+    let mut code_only = false;
+    let mut dry_run = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-j" => match args.next() {
+                Some(path) => journal_mapfile = Some(Path::new(path)),
+                None => {
+                    log_err!("Expected a mapfile after '-j'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-s" => code_only = true,
+            "-n" | "--dry-run" => dry_run = true,
+            arg => log_err!("Unknown argument '{}'", arg),
+        }
+    }
+    
+    let renames = match journal_mapfile {
+        Some(mapfile_path) => {
+            let batches = store::read_journal(mapfile_path)
+                .map_err(|e| e.to_string())
+                .and_then(|journal| Batch::parse_journal(&journal).map_err(|e| e.to_string()));
+            match batches {
+                Ok(batches) => batches.last().map(refactor::batch_renames).unwrap_or_default(),
+                Err(e) => {
+                    log_err!("Failed to read journal of {}: {}", mapfile_path.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        None => {
+            let mut renames = Renames::new();
+            for line in stdin().lock().lines() {
+                let Ok(line) = line else { continue };
+                if let Some((old, new)) = refactor::parse_rename_line(&line) {
+                    renames.insert(old, new);
+                }
+            }
+            renames
+        }
+    };
+    
+    if renames.is_empty() { return ExitCode::SUCCESS }
+    
+    let mut paths = files_in_path(Path::new(search_path), |path, e| {
+        log_err!("Failed to read directory {}: {}", path.display(), e);
+    });
+    paths.sort();
+    
+    let mut total = 0;
+    let mut files = 0;
+    for path in paths {
+        let Some(ext) = path.extension() else { continue };
+        if !extract::SOURCE_EXTENSIONS.iter().any(|allowed_ext| ext == *allowed_ext) { continue }
+        
+        let src = match std::fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                log_err!("Failed to read file {}: {}", path.display(), e);
+                continue
+            }
+        };
+        
+        let rewrite = refactor::rewrite(&src, &renames, code_only);
+        if rewrite.counts.is_empty() { continue }
+        
+        println!("{}: {} replacements", path.display(), rewrite.replacements());
+        for (old, count) in rewrite.counts.iter() {
+            println!("    {} -> {} x{}", old, renames[old], count);
+        }
+        total += rewrite.replacements();
+        files += 1;
+        
+        if !dry_run && let Err(e) = store::write_atomic(&path, rewrite.src.as_bytes()) {
+            log_err!("Failed to write {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+    
+    println!("{} replacements in {} files", total, files);
+    
+    ExitCode::SUCCESS
+}
 
-struct SymAddr<'a> {
-    addr: u32,
-    _addr_range: Range<usize>,
+fn check(args: &[String]) -> ExitCode {
+    if args.is_empty() {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let (mapfile_path, args) = args.split_last().unwrap();
+    
+    let mut fail_on_warnings = false;
+    let mut quiet = false;
+    for arg in args {
+        match arg.as_str() {
+            "-w" => fail_on_warnings = true,
+            "-q" => quiet = true,
+            arg => log_err!("Unknown argument '{}'", arg),
+        }
+    }
+    
+    let Some(mapfile) = read_mapfile(Path::new(mapfile_path)) else { return ExitCode::FAILURE };
+    
+    let mut errors = 0;
+    let mut warnings = 0;
+    for problem in check::check(&mapfile) {
+        if problem.kind.is_error() {
+            errors += 1;
+        } else {
+            warnings += 1;
+            if quiet { continue }
+        }
+        println!("{}", problem);
+    }
+    
+    println!("{} errors, {} warnings", errors, warnings);
+    
+    if errors != 0 || (fail_on_warnings && warnings != 0) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
 
-    symbol: &'a str,
-    symbol_range: Range<usize>,
+fn diff(args: &[String]) -> ExitCode {
+    if args.len() < 2 {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let (args, paths) = args.split_at(args.len() - 2);
+    
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "-j" => json = true,
+            arg => log_err!("Unknown argument '{}'", arg),
+        }
+    }
+    
+    let Some(old) = read_mapfile(Path::new(&paths[0])) else { return ExitCode::FAILURE };
+    let Some(new) = read_mapfile(Path::new(&paths[1])) else { return ExitCode::FAILURE };
+    
+    let diff = diff::diff(&old, &new);
+    
+    let mut out = String::new();
+    if json {
+        diff.write_json(&mut out).unwrap();
+    } else {
+        out = diff.to_string();
+    }
+    print!("{}", out);
+    
+    ExitCode::SUCCESS
 }
 
-fn line_symaddr(line: &str) -> Option<SymAddr> {
-    // find address ----------------------------------
-    
-    let mut addr = 0;
-    let mut addr_start = 0;
-    'addr_window: for (i, addr_bytes) in line.as_bytes().windows(8).enumerate() {
-        let mut cur_addr = 0;
-        for b in addr_bytes {
-            let n = match b {
-                b'0'..=b'9' => (b - b'0') as u32,
-                b'a'..=b'f' => (b - b'a' + 10) as u32,
-                b'A'..=b'F' => (b - b'A' + 10) as u32,
-                _ => continue 'addr_window,
-            };
-            cur_addr = (cur_addr << 4) | n;
+fn merge(args: &[String]) -> ExitCode {
+    if args.len() < 3 {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let (args, paths) = args.split_at(args.len() - 3);
+    
+    let mut print = false;
+    for arg in args {
+        match arg.as_str() {
+            "-p" => print = true,
+            arg => log_err!("Unknown argument '{}'", arg),
         }
-        
-        if 0x80000000 <= cur_addr && cur_addr < 0x81800000 {
-            addr = cur_addr;
-            addr_start = i;
-            break;
+    }
+    
+    let ours_path = Path::new(&paths[1]);
+    let Some(base) = read_mapfile(Path::new(&paths[0])) else { return ExitCode::FAILURE };
+    let Some(ours) = read_mapfile(ours_path) else { return ExitCode::FAILURE };
+    let Some(theirs) = read_mapfile(Path::new(&paths[2])) else { return ExitCode::FAILURE };
+    
+    let merged = merge::merge(&base, &ours, &theirs);
+    
+    if print {
+        print!("{}", merged);
+    } else if let Err(e) = store::write_atomic(ours_path, merged.to_string().as_bytes()) {
+        log_err!("Failed to write map file {}: {}", ours_path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    
+    let conflicts = merged.conflicts();
+    if conflicts != 0 {
+        log_err!("{} conflicting entries", conflicts);
+        return ExitCode::FAILURE;
+    }
+    
+    ExitCode::SUCCESS
+}
+
+fn stats(args: &[String]) -> ExitCode {
+    if args.is_empty() {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let (mapfile_path, args) = args.split_last().unwrap();
+    
+    let mut markdown = false;
+    let mut json = false;
+    let mut region_size = 0x40000;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" => markdown = true,
+            "-j" => json = true,
+            "-r" => match args.next().map(|s| u32::from_str_radix(s.trim_start_matches("0x"), 16)) {
+                Some(Ok(size)) if size != 0 => region_size = size,
+                _ => {
+                    log_err!("Expected a nonzero hex region size after '-r'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            arg => log_err!("Unknown argument '{}'", arg),
         }
     }
     
-    // addr not found on this line
-    if addr == 0 { return None }
+    let Some(mapfile) = read_mapfile(Path::new(mapfile_path)) else { return ExitCode::FAILURE };
+    let stats = stats::stats(&mapfile, region_size);
+    
+    let mut out = String::new();
+    if json {
+        stats.write_json(&mut out).unwrap();
+    } else if markdown {
+        stats.write_markdown(&mut out).unwrap();
+    } else {
+        stats.write_text(&mut out).unwrap();
+    }
+    print!("{}", out);
+    
+    ExitCode::SUCCESS
+}
+
+fn export(args: &[String]) -> ExitCode {
+    if args.is_empty() {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
     
-    // find symbol ----------------------------------
+    let (mapfile_path, args) = args.split_last().unwrap();
     
-    let mut chars = line.char_indices();
+    let mut format = None;
+    let mut out_path = None;
+    let mut filter = Filter::default();
+    let mut options = ExportOptions::default();
+    let mut prototypes_path = None;
+    let mut splits_path = None;
+    let mut split_dir = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(|s| (s, Format::parse(s))) {
+                Some((_, Some(f))) => format = Some(f),
+                Some((name, None)) => {
+                    log_err!("Unknown format '{}', expected one of {}", name, format_names(Format::ALL));
+                    return ExitCode::FAILURE;
+                }
+                None => {
+                    log_err!("Expected a format after '--format'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-o" => match args.next() {
+                Some(path) => out_path = Some(Path::new(path)),
+                None => {
+                    log_err!("Expected a path after '-o'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--named" => filter.named_only = true,
+            "-g" => match args.next() {
+                Some(glob) => filter.globs.push(glob.clone()),
+                None => {
+                    log_err!("Expected a glob after '-g'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-p" => match args.next() {
+                Some(path) => prototypes_path = Some(Path::new(path)),
+                None => {
+                    log_err!("Expected a path after '-p'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-s" => match args.next() {
+                Some(path) => splits_path = Some(Path::new(path)),
+                None => {
+                    log_err!("Expected a path after '-s'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--extern" => options.header.externs = true,
+            "--abs" => options.absolute = true,
+            "--split" => match args.next() {
+                Some(path) => split_dir = Some(Path::new(path)),
+                None => {
+                    log_err!("Expected a directory after '--split'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            arg => log_err!("Unknown argument '{}'", arg),
+        }
+    }
     
-    let start_i = 'find_start_i: loop {
-        loop {
-            match chars.next() {
-                // don't parse hex numbers as a symbol 
-                Some((_, c)) if c.is_numeric() => break,
+    let Some(format) = format else {
+        log_err!("Expected '--format <format>', one of {}", format_names(Format::ALL));
+        return ExitCode::FAILURE;
+    };
+    
+    let Some(mapfile) = read_mapfile(Path::new(mapfile_path)) else { return ExitCode::FAILURE };
+    
+    let mapfile = filter.apply(&mapfile);
+    
+    if matches!(format, Format::Dtk | Format::Json | Format::Csv | Format::Tsv) {
+        let objects = match splits_path {
+            Some(path) => parse_file(path, dtk::parse_splits),
+            None => read_objects(Path::new(mapfile_path)),
+        };
+        let Some(objects) = objects else { return ExitCode::FAILURE };
+        options.objects = objects;
+    }
+    
+    if let Some(path) = prototypes_path {
+        let Some(src) = read_file(path) else { return ExitCode::FAILURE };
+        for prototype in src.lines().filter_map(Prototype::parse) {
+            options.header.prototypes.insert(prototype.name.clone(), prototype);
+        }
+    }
+    
+    if format == Format::Header && let Some(dir) = split_dir {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            log_err!("Failed to create directory {}: {}", dir.display(), e);
+            return ExitCode::FAILURE;
+        }
+        
+        for (stem, part) in header::split_by_prefix(&mapfile) {
+            let out = export::export(&part, Format::Header, &options);
+            
+            let path = dir.join(format!("{}.h", stem));
+            if let Err(e) = store::write_atomic(&path, &out) {
+                log_err!("Failed to write {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+    
+    let out = export::export(&mapfile, format, &options);
+    
+    let res = match out_path {
+        Some(path) => store::write_atomic(path, &out),
+        None => stdout().write_all(&out),
+    };
+    if let Err(e) = res && e.kind() != ErrorKind::BrokenPipe {
+        log_err!("Failed to write {}: {}", out_path.map_or("stdout".into(), |p| p.display().to_string()), e);
+        return ExitCode::FAILURE;
+    }
+    
+    ExitCode::SUCCESS
+}
 
-                Some((i, c)) if c.is_ascii_alphabetic() || c == '_' => break 'find_start_i i,
-                None => return None,
-                _ => {}
+fn import(args: &[String]) -> ExitCode {
+    if args.len() < 2 {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let (args, paths) = args.split_at(args.len() - 2);
+    let input_path = Path::new(&paths[0]);
+    let mapfile_path = Path::new(&paths[1]);
+    
+    let mut options = UpdateOptions { layout: true, ..UpdateOptions::default() };
+    let mut format = None;
+    let mut splits_path = None;
+    let mut dry_run = false;
+    let mut check_only = false;
+    let mut note = "";
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(|s| (s, Format::parse(s))) {
+                Some((_, Some(f))) if Format::IMPORTABLE.contains(&f) => format = Some(f),
+                Some((name, _)) => {
+                    log_err!("Cannot import format '{}', expected one of {}", name, format_names(Format::IMPORTABLE));
+                    return ExitCode::FAILURE;
+                }
+                None => {
+                    log_err!("Expected a format after '--format'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-s" => match args.next() {
+                Some(path) => splits_path = Some(Path::new(path)),
+                None => {
+                    log_err!("Expected a path after '-s'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-f" | "--force" => options.force = true,
+            "-m" | "--move" => options.move_duplicates = true,
+            "-i" | "--insert" => options.insert = true,
+            "-n" | "--dry-run" => dry_run = true,
+            "-c" | "--check" => check_only = true,
+            "--note" => match args.next() {
+                Some(text) => note = text.as_str(),
+                None => {
+                    log_err!("Expected a note after '--note'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            arg => log_err!("Unknown argument '{}'", arg),
+        }
+    }
+    
+    let Some(format) = format else {
+        log_err!("Expected '--format <format>', one of {}", format_names(Format::IMPORTABLE));
+        return ExitCode::FAILURE;
+    };
+    
+    let mut records = Vec::new();
+    let mut table = None;
+    match format {
+        Format::Dtk => match parse_file(input_path, dtk::parse_symbols) {
+            Some(r) => records = r,
+            None => return ExitCode::FAILURE,
+        },
+        Format::Json | Format::Csv | Format::Tsv => {
+            let parse = match format {
+                Format::Json => table::parse_json,
+                Format::Csv => table::parse_csv,
+                _ => table::parse_tsv,
+            };
+            match parse_file(input_path, parse) {
+                Some(t) => table = Some(t),
+                None => return ExitCode::FAILURE,
+            }
+        }
+        _ => unreachable!("checked against Format::IMPORTABLE"),
+    }
+    
+    let splits = match splits_path {
+        Some(path) => match parse_file(path, dtk::parse_splits) {
+            Some(splits) => Some(splits),
+            None => return ExitCode::FAILURE,
+        },
+        None => None,
+    };
+    
+    let Some(_lock) = lock_mapfile(mapfile_path) else { return ExitCode::FAILURE };
+    let Some(mut mapfile) = read_mapfile(mapfile_path) else { return ExitCode::FAILURE };
+    let original = mapfile.clone();
+    
+    if let Some(table) = table {
+        // tables hold the whole map, so they replace it instead of being merged
+        let problems = table::validate(&original, &table, options.force);
+        if !problems.is_empty() {
+            for problem in problems.iter() {
+                println!("{}", problem);
+            }
+            log_err!("Not importing {}, it has the problems above", input_path.display());
+            return ExitCode::FAILURE;
+        }
+        mapfile = table;
+        if dry_run || check_only {
+            print!("{}", map_diff(mapfile_path, &original, &mapfile));
+        } else {
+            print!("{}", diff::diff(&original, &mapfile));
+        }
+    } else {
+        // most imported names are the tool's own placeholders, keep their layout but not the name
+        if !options.force {
+            let names = mapfile.entries().map(|e| (e.start, e.name.as_str())).collect::<HashMap<_, _>>();
+            for record in records.iter_mut().filter(|r| map::is_placeholder(&r.name)) {
+                record.name = match names.get(&record.addr) {
+                    Some(name) => name.to_string(),
+                    None => map::placeholder_name(record.addr),
+                };
             }
         }
         
-        // skip hex digits
-        loop {
-            match chars.next() {
-                Some((_, c)) if !c.is_ascii_hexdigit() => break,
-                None => return None,
-                _ => {}
+        let report = update::apply(&mut mapfile, &records, options);
+        print_update_report(mapfile_path, &original, &mapfile, &report, dry_run || check_only);
+    }
+    
+    if check_only {
+        return if mapfile == original { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+    }
+    if dry_run { return ExitCode::SUCCESS }
+    
+    if !save_mapfile(mapfile_path, &original, &mapfile, note) { return ExitCode::FAILURE }
+    
+    if let Some(splits) = splits {
+        if let Err(e) = store::write_objects(mapfile_path, &splits) {
+            log_err!("Failed to write object files of {}: {}", mapfile_path.display(), e);
+            return ExitCode::FAILURE;
+        }
+        println!("recorded {} object file ranges", splits.len());
+    }
+    
+    ExitCode::SUCCESS
+}
+
+fn undo(args: &[String]) -> ExitCode {
+    if args.is_empty() {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    
+    let (mapfile_path, args) = args.split_last().unwrap();
+    let mapfile_path = Path::new(mapfile_path);
+    
+    let mut dry_run = false;
+    let mut list = false;
+    let mut count = 1;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" | "--dry-run" => dry_run = true,
+            "-l" => list = true,
+            "-N" => match args.next().map(|s| s.parse::<usize>()) {
+                Some(Ok(n)) if n != 0 => count = n,
+                _ => {
+                    log_err!("Expected a number of batches of at least 1 after '-N'");
+                    return ExitCode::FAILURE;
+                }
+            },
+            // undo rewrites the map, so don't guess what was meant
+            arg => {
+                log_err!("Unknown argument '{}'", arg);
+                return ExitCode::FAILURE;
             }
         }
+    }
+    
+    let Some(_lock) = lock_mapfile(mapfile_path) else { return ExitCode::FAILURE };
+    
+    let journal = match store::read_journal(mapfile_path) {
+        Ok(journal) => journal,
+        Err(e) => {
+            log_err!("Failed to read journal of {}: {}", mapfile_path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut batches = match Batch::parse_journal(&journal) {
+        Ok(batches) => batches,
+        Err(e) => {
+            log_err!("Failed to parse journal of {}: {}", mapfile_path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    
+    if list {
+        for batch in batches.iter() {
+            println!("{} {} ({} changes)", batch.timestamp, batch.command, batch.changes.len());
+        }
+        return ExitCode::SUCCESS;
+    }
+    
+    if batches.len() < count {
+        log_err!("Only {} batches in the journal of {}", batches.len(), mapfile_path.display());
+        return ExitCode::FAILURE;
+    }
+    
+    let Some(mut mapfile) = read_mapfile(mapfile_path) else { return ExitCode::FAILURE };
+    let original = mapfile.clone();
+    
+    // newest batch first
+    for batch in batches.iter().rev().take(count) {
+        if let Err(e) = batch.revert(&mut mapfile) {
+            log_err!("Cannot undo batch {} {}: {}", batch.timestamp, batch.command, e);
+            return ExitCode::FAILURE;
+        }
+        if !dry_run {
+            println!("undid {} {} ({} changes)", batch.timestamp, batch.command, batch.changes.len());
+        }
+    }
+    
+    if dry_run {
+        print!("{}", map_diff(mapfile_path, &original, &mapfile));
+        return ExitCode::SUCCESS;
+    }
+    
+    let mapfile_str = mapfile.to_string();
+    if let Err(e) = store::write_backup(mapfile_path, original.to_string().as_bytes())
+        .and_then(|()| store::write_atomic(mapfile_path, mapfile_str.as_bytes()))
+    {
+        log_err!("Failed to write map file {}: {}", mapfile_path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    
+    let undone = batches.split_off(batches.len() - count);
+    if let Err(e) = store::write_journal(mapfile_path, &batches) {
+        log_err!("Failed to write journal of {}: {}", mapfile_path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    
+    // the names are back to how they were, which the history records like any other edit
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let note = format!("undo of {}", undone.iter().rev().map(|b| b.command.as_str()).collect::<Vec<_>>().join("; "));
+    let name_changes = history::name_changes(&journal::changes(&original, &mapfile), timestamp, &author(), &note);
+    if let Err(e) = store::append_history(mapfile_path, &name_changes) {
+        log_err!("Failed to write history of {}: {}", mapfile_path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    
+    ExitCode::SUCCESS
+}
+
+fn history(args: &[String]) -> ExitCode {
+    let [query, mapfile_path] = args else {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
     };
+    let mapfile_path = Path::new(mapfile_path);
     
-    let end_i = loop {
-        match chars.next() {
-            Some((_, c)) if c.is_ascii_alphanumeric() || c == '_' => {},
-            Some((i, _)) => break i,
-            None => break chars.offset(),
+    let changes = store::read_history(mapfile_path)
+        .map_err(|e| e.to_string())
+        .and_then(|src| history::parse_history(&src).map_err(|e| e.to_string()));
+    let changes = match changes {
+        Ok(changes) => changes,
+        Err(e) => {
+            log_err!("Failed to read history of {}: {}", mapfile_path.display(), e);
+            return ExitCode::FAILURE;
         }
     };
+    let Some(mapfile) = read_mapfile(mapfile_path) else { return ExitCode::FAILURE };
     
-    let symbol = &line[start_i..end_i];
+    let addrs = history::query_addrs(&changes, &mapfile, query);
     
-    Some(SymAddr {
-        addr,
-        _addr_range: addr_start..addr_start+8,
-        symbol,
-        symbol_range: start_i..end_i,
-    })
+    if addrs.is_empty() {
+        log_err!("No entry is or was named '{}'", query);
+        return ExitCode::FAILURE;
+    }
+    
+    for (i, &addr) in addrs.iter().enumerate() {
+        if i != 0 { println!(); }
+        
+        match mapfile.entry_at(addr) {
+            Some((section, entry)) => println!("{:08x} {} {}", addr, entry.name, section.name),
+            None => println!("{:08x} (no longer in map)", addr),
+        }
+        
+        let timeline = history::timeline(&changes, addr);
+        if timeline.is_empty() {
+            println!("    no recorded changes");
+        }
+        for change in timeline {
+            print_name_change(change);
+        }
+    }
+    
+    ExitCode::SUCCESS
 }
 
-fn take_while<'a>(src: &mut CharIndices<'a>, f: fn(char) -> bool) -> &'a str {
-    let start_i = src.offset();
-    let rest = src.as_str();
+// Helper functions --------------------------------------------------------
 
-    loop {
-        match src.as_str().chars().next() {
-            Some(c) if f(c) => src.next(),
-            _ => break,
-        };
+/// Prints the changes of an update, as a diff on dry runs and checks, followed by the skipped records.
+fn print_update_report(path: &Path, old: &MapFile, new: &MapFile, report: &update::UpdateReport, dry_run: bool) {
+    if dry_run {
+        print!("{}", map_diff(path, old, new));
+    } else {
+        for inserted in report.inserted.iter() {
+            println!("inserted {} {:08x} size {:x} {}", inserted.entry.name, inserted.entry.start, inserted.entry.size, inserted.section);
+        }
+        for resized in report.resized.iter() {
+            println!("resized {} {:08x} {:x} -> {:x} {}", resized.name, resized.addr, resized.old_size, resized.new_size, resized.section);
+        }
+        for r in report.realigned.iter() {
+            println!(
+                "realigned {} {:08x} vaddr {:08x} align {} -> vaddr {:08x} align {} {}",
+                r.name, r.addr, r.old_vaddr, r.old_align, r.new_vaddr, r.new_align, r.section,
+            );
+        }
+        for rename in report.renamed.iter() {
+            println!("{} -> {} {}", rename.old, rename.new, rename.section);
+        }
     }
+    
+    if !report.skipped.is_empty() {
+        println!("skipped {} conflicting updates:", report.skipped.len());
+        for skipped in report.skipped.iter() {
+            println!("    {}", skipped);
+        }
+    }
+}
 
-    let end_i = src.offset();
-    &rest[..(end_i - start_i)]
+fn format_names(formats: &[Format]) -> String {
+    formats.iter().map(|f| f.name()).collect::<Vec<_>>().join(", ")
 }
 
-fn take_whitespace<'a>(src: &mut CharIndices<'a>) -> &'a str {
-    take_while(src, |c| c.is_ascii_whitespace())
+fn read_file(path: &Path) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(src) => Some(src),
+        Err(e) => {
+            log_err!("Failed to read {}: {}", path.display(), e);
+            None
+        }
+    }
 }
 
-fn take_c_token<'a>(src: &mut CharIndices<'a>) -> &'a str {
-    let start_i = src.offset();
-    let rest = src.as_str();
+/// Reads and parses a file, logging any error.
+fn parse_file<T>(path: &Path, parse: fn(&str) -> std::result::Result<T, ParseError>) -> Option<T> {
+    let src = read_file(path)?;
     
-    'check_token: {
-        // initial character check to prevent starting with number
-        match src.as_str().chars().next() {
-            Some(c) if c.is_ascii_alphabetic() || c == '_' => src.next(),
-            _ => break 'check_token,
-        };
+    match parse(&src) {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            log_err!("Failed to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
 
-        // allow numbers in proceeding characters
-        loop {
-            match src.as_str().chars().next() {
-                Some(c) if c.is_ascii_alphanumeric() || c == '_' => src.next(),
-                _ => break 'check_token,
-            };
+/// Reads the object file address ranges recorded for a map.
+fn read_objects(path: &Path) -> Option<Vec<Split>> {
+    let src = match store::read_objects(path) {
+        Ok(src) => src,
+        Err(e) => {
+            log_err!("Failed to read object files of {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    
+    let mut splits = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        match Split::parse(line) {
+            Ok(split) => splits.push(split),
+            Err(kind) => {
+                log_err!("Failed to parse object files of {}: {}", path.display(), ParseError { line: i + 1, kind });
+                return None;
+            }
+        }
+    }
+    Some(splits)
+}
+
+fn print_name_change(change: &NameChange) {
+    let old = change.old.as_deref().unwrap_or("(inserted)");
+    let new = change.new.as_deref().unwrap_or("(removed)");
+    print!("    {}  {} -> {}  by {}", history::format_timestamp(change.timestamp), old, new, change.author);
+    if !change.note.is_empty() {
+        print!("  ({})", change.note);
+    }
+    println!();
+}
+
+fn lock_mapfile(path: &Path) -> Option<store::MapLock> {
+    match store::lock(path) {
+        Ok(lock) => Some(lock),
+        Err(e) => {
+            log_err!("Failed to lock map file {}: {}", path.display(), e);
+            None
         }
     }
+}
 
-    let end_i = src.offset();
-    &rest[..(end_i - start_i)]
+/// Writes an edited map, keeping a backup of the old map and recording the edit in the journal
+/// and its name changes in the history.
+fn save_mapfile(path: &Path, old: &MapFile, new: &MapFile, note: &str) -> bool {
+    let changes = journal::changes(old, new);
+    if changes.is_empty() { return true }
+    
+    let res = store::write_backup(path, old.to_string().as_bytes())
+        .and_then(|()| store::write_atomic(path, new.to_string().as_bytes()));
+    if let Err(e) = res {
+        log_err!("Failed to write map file {}: {}", path.display(), e);
+        return false;
+    }
+    
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    
+    let name_changes = history::name_changes(&changes, timestamp, &author(), note);
+    if let Err(e) = store::append_journal(path, &Batch { timestamp, command, changes }) {
+        log_err!("Failed to write journal of {}: {}", path.display(), e);
+        return false;
+    }
+    if let Err(e) = store::append_history(path, &name_changes) {
+        log_err!("Failed to write history of {}: {}", path.display(), e);
+        return false;
+    }
+    
+    true
 }
 
-fn files_in_path(root_path: &Path) -> Vec<PathBuf> {
-    if root_path.is_file() {
-        return vec![root_path.to_owned()];
+/// Author of edits for the history: $SYMTOOL_AUTHOR, else git's user.name, else $USER.
+fn author() -> String {
+    if let Ok(author) = std::env::var("SYMTOOL_AUTHOR") && !author.is_empty() {
+        return author;
     }
     
-    let mut files = Vec::new();
-    let mut dir_stack = Vec::new();
-    dir_stack.push(root_path.to_owned());
+    let git_name = std::process::Command::new("git")
+        .args(["config", "user.name"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok());
+    if let Some(name) = git_name && !name.trim().is_empty() {
+        return name.trim().to_string();
+    }
     
-    while let Some(path) = dir_stack.pop() {
-        let iter = match std::fs::read_dir(&path) {
-            Ok(iter) => iter,
-            Err(e) => {
-                log_err!("Failed to read directory {}: {}", path.display(), e);
-                continue
-            }
-        };
+    std::env::var("USER").unwrap_or_else(|_| "unknown".to_string())
+}
 
-        for entry in iter {
-            let Ok(entry) = entry else { continue };
-            let Ok(metadata) = entry.metadata() else { continue };
-            
-            let name = entry.file_name();
-            let new_path = path.join(name);
-            
-            let file_type = metadata.file_type();
-            if file_type.is_dir() {
-                dir_stack.push(new_path);
-            } else if file_type.is_file() {
-                files.push(new_path);
-            }
+/// Unified diff of a map edit, for previews.
+fn map_diff(path: &Path, old: &MapFile, new: &MapFile) -> String {
+    let path = path.display().to_string();
+    let path = path.trim_start_matches('/');
+    let old_name = format!("a/{}", path);
+    let new_name = format!("b/{}", path);
+    unidiff::unified_diff(&old.to_string(), &new.to_string(), &old_name, &new_name, 3)
+}
+
+fn read_mapfile(path: &Path) -> Option<MapFile> {
+    let src = match std::fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => {
+            log_err!("Failed to read map file {}: {}", path.display(), e);
+            return None;
         }
-    }
+    };
     
-    files
+    match MapFile::parse(&src) {
+        Ok(mapfile) => Some(mapfile),
+        Err(e) => {
+            log_err!("Failed to parse map file {}: {}", path.display(), e);
+            None
+        }
+    }
 }
@@ -0,0 +1,356 @@
+//! Parsing, looking up and editing Dolphin symbol maps.
+
+use std::fmt;
+
+// Dolphin map files look like:
+//
+//     .text section layout
+//     80000100 000098 80000100 0 system_reset_exception_handler
+//     80000200 000098 80000200 0 machine_check_exception_handler
+//     ...
+//
+//     .data section layout
+//
+// Each section starts with a '<name> section layout' header, followed by
+// 'start size vaddr align name' entries. Sections are separated by a blank line.
+
+/// A parsed Dolphin symbol map.
+///
+/// Formatting a `MapFile` with `Display` writes it back out in Dolphin's format.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MapFile {
+    /// Sections in file order.
+    pub sections: Vec<Section>,
+}
+
+/// A section of the map, from its header to the next blank line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    /// Section name including the leading dot, e.g. ".text".
+    pub name: String,
+    /// Entries in file order, which is normally address order.
+    pub entries: Vec<MapEntry>,
+}
+
+/// A single 'start size vaddr align name' line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapEntry {
+    /// Start address.
+    pub start: u32,
+    /// Size in bytes.
+    pub size: u32,
+    /// Virtual address, the same as `start` in every map Dolphin writes.
+    pub vaddr: u32,
+    /// Alignment in bytes, 0 if unknown.
+    pub align: u32,
+    /// Symbol name, or a placeholder such as `zz_80003100_`.
+    pub name: String,
+}
+
+/// A line of a map, or of another symbol file, that could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    /// What is wrong with the line.
+    pub kind: ParseErrorKind,
+}
+
+/// What is wrong with a line that could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// An entry comes before the first section header.
+    EntryOutsideSection,
+    /// The named column is missing or empty.
+    MissingColumn(&'static str),
+    /// The named column is not a hex number.
+    InvalidHex(&'static str),
+    /// The align column is not a number.
+    InvalidAlign,
+    /// Any other error, described by the message.
+    Syntax(&'static str),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            ParseErrorKind::EntryOutsideSection => write!(f, "entry before any section header"),
+            ParseErrorKind::MissingColumn(col) => write!(f, "missing {} column", col),
+            ParseErrorKind::InvalidHex(col) => write!(f, "{} column is not a hex number", col),
+            ParseErrorKind::InvalidAlign => write!(f, "align column is not a number"),
+            ParseErrorKind::Syntax(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Section headers are the section name followed by this, e.g. ".text section layout".
+pub const SECTION_HEADER_SUFFIX: &str = " section layout";
+
+/// Names of the sections a GameCube DOL can contain.
+pub const SECTION_NAMES: &[&str] = &[
+    ".init", ".text",
+    ".extab", ".extabindex", ".ctors", ".dtors", ".rodata",
+    ".data", ".bss", ".sdata", ".sbss", ".sdata2", ".sbss2",
+];
+
+/// Names of the sections that hold code. All other sections hold data.
+pub const CODE_SECTIONS: &[&str] = &[".init", ".text"];
+
+impl MapFile {
+    /// Parses the contents of a Dolphin map file.
+    pub fn parse(src: &str) -> Result<MapFile, ParseError> {
+        let mut sections = Vec::<Section>::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line_num = i + 1;
+            let line = line.trim();
+            if line.is_empty() { continue }
+
+            if let Some(name) = line.strip_suffix(SECTION_HEADER_SUFFIX) {
+                sections.push(Section { name: name.trim().to_string(), entries: Vec::new() });
+                continue;
+            }
+
+            let Some(section) = sections.last_mut() else {
+                return Err(ParseError { line: line_num, kind: ParseErrorKind::EntryOutsideSection });
+            };
+
+            let entry = MapEntry::parse(line)
+                .map_err(|kind| ParseError { line: line_num, kind })?;
+            section.entries.push(entry);
+        }
+
+        Ok(MapFile { sections })
+    }
+
+    /// Returns the section named `name`, e.g. ".text".
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Returns the section named `name` for editing.
+    pub fn section_mut(&mut self, name: &str) -> Option<&mut Section> {
+        self.sections.iter_mut().find(|s| s.name == name)
+    }
+
+    /// All entries of all sections, in file order.
+    pub fn entries(&self) -> impl Iterator<Item=&MapEntry> {
+        self.sections.iter().flat_map(|s| s.entries.iter())
+    }
+
+    /// All entries of all sections for editing, in file order.
+    pub fn entries_mut(&mut self) -> impl Iterator<Item=&mut MapEntry> {
+        self.sections.iter_mut().flat_map(|s| s.entries.iter_mut())
+    }
+
+    /// All entries of all sections along with their section, in file order.
+    pub fn section_entries(&self) -> impl Iterator<Item=(&Section, &MapEntry)> {
+        self.sections.iter().flat_map(|s| s.entries.iter().map(move |e| (s, e)))
+    }
+
+    /// Returns the first entry named `name`.
+    pub fn entry_by_name(&self, name: &str) -> Option<(&Section, &MapEntry)> {
+        self.section_entries().find(|(_, e)| e.name == name)
+    }
+
+    /// Returns the entry starting at `addr`.
+    pub fn entry_at(&self, addr: u32) -> Option<(&Section, &MapEntry)> {
+        self.section_entries().find(|(_, e)| e.start == addr)
+    }
+}
+
+/// Whether `name` is a valid C identifier.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Prefixes of auto-generated names: Dolphin's `zz_`, decomp-toolkit's `fn_` and `lbl_`,
+/// Ghidra's `FUN_` and IDA's `sub_`. Each is followed by the 8 digit hex address.
+pub const PLACEHOLDER_PREFIXES: &[&str] = &["zz_", "fn_", "FUN_", "sub_", "lbl_"];
+
+/// Returns the address embedded in a placeholder name such as `zz_80003100_` or `FUN_80003100`.
+pub fn placeholder_addr(name: &str) -> Option<u32> {
+    let rest = PLACEHOLDER_PREFIXES.iter().find_map(|prefix| name.strip_prefix(prefix))?;
+    let hex = rest.get(..8)?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) { return None }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// Returns Dolphin's placeholder name for an address, e.g. `zz_80003100_`.
+pub fn placeholder_name(addr: u32) -> String {
+    format!("zz_{:08x}_", addr)
+}
+
+/// Whether `name` is an auto-generated placeholder rather than a real name.
+pub fn is_placeholder(name: &str) -> bool {
+    placeholder_addr(name).is_some()
+}
+
+impl Section {
+    /// Whether this section holds functions rather than variables.
+    pub fn is_code(&self) -> bool {
+        CODE_SECTIONS.contains(&self.name.as_str())
+    }
+
+    /// Inserts an entry after all entries starting at or before it.
+    pub fn insert_sorted(&mut self, entry: MapEntry) {
+        let i = self.entries.partition_point(|e| e.start <= entry.start);
+        self.entries.insert(i, entry);
+    }
+}
+
+impl MapEntry {
+    /// One past the last byte covered by this entry.
+    pub fn end(&self) -> u32 {
+        self.start.wrapping_add(self.size)
+    }
+
+    /// Parses a single 'start size vaddr align name' line.
+    pub fn parse(line: &str) -> Result<MapEntry, ParseErrorKind> {
+        let mut rest = line;
+        let start = take_column(&mut rest, "start")?;
+        let size = take_column(&mut rest, "size")?;
+        let vaddr = take_column(&mut rest, "vaddr")?;
+        let align = take_column(&mut rest, "align")?;
+
+        // the name is the rest of the line
+        let name = rest.trim();
+        if name.is_empty() { return Err(ParseErrorKind::MissingColumn("name")) }
+
+        let hex = |s, col| u32::from_str_radix(s, 16).map_err(|_| ParseErrorKind::InvalidHex(col));
+
+        Ok(MapEntry {
+            start: hex(start, "start")?,
+            size: hex(size, "size")?,
+            vaddr: hex(vaddr, "vaddr")?,
+            align: align.parse().map_err(|_| ParseErrorKind::InvalidAlign)?,
+            name: name.to_string(),
+        })
+    }
+}
+
+fn take_column<'a>(rest: &mut &'a str, col: &'static str) -> Result<&'a str, ParseErrorKind> {
+    let s = rest.trim_start();
+    if s.is_empty() { return Err(ParseErrorKind::MissingColumn(col)) }
+    
+    let end = s.find(|c: char| c.is_ascii_whitespace()).unwrap_or(s.len());
+    *rest = &s[end..];
+    Ok(&s[..end])
+}
+
+impl fmt::Display for MapFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, section) in self.sections.iter().enumerate() {
+            if i != 0 { writeln!(f)?; }
+            write!(f, "{}", section)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{}", self.name, SECTION_HEADER_SUFFIX)?;
+        for entry in self.entries.iter() {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MapEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x} {:06x} {:08x} {} {}", self.start, self.size, self.vaddr, self.align, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80003130 0000c4 80003130 0 zz_80003130_
+
+.data section layout
+
+.sdata section layout
+804d36a0 000004 804d36a0 4 some_global
+";
+
+    #[test]
+    fn parse_display_round_trip() {
+        let mapfile = MapFile::parse(SAMPLE).unwrap();
+        assert_eq!(mapfile.to_string(), SAMPLE);
+    }
+
+    #[test]
+    fn round_trip_gale01() {
+        let src = include_str!("../../GALE01.map");
+        let mapfile = MapFile::parse(src).unwrap();
+        assert_eq!(mapfile.to_string(), src);
+    }
+
+    #[test]
+    fn parse_sections_and_entries() {
+        let mapfile = MapFile::parse(SAMPLE).unwrap();
+        let names = mapfile.sections.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, [".text", ".data", ".sdata"]);
+
+        let (section, entry) = mapfile.entry_by_name("some_global").unwrap();
+        assert_eq!(section.name, ".sdata");
+        assert_eq!(*entry, MapEntry { start: 0x804d36a0, size: 4, vaddr: 0x804d36a0, align: 4, name: "some_global".into() });
+        assert!(mapfile.section(".text").unwrap().is_code());
+        assert!(!section.is_code());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = MapFile::parse("80003100 000030 80003100 0 memset\n").unwrap_err();
+        assert_eq!(err, ParseError { line: 1, kind: ParseErrorKind::EntryOutsideSection });
+
+        let err = MapFile::parse(".text section layout\n80003100 000030 80003100 0\n").unwrap_err();
+        assert_eq!(err, ParseError { line: 2, kind: ParseErrorKind::MissingColumn("name") });
+
+        let err = MapFile::parse(".text section layout\n8000310g 000030 80003100 0 memset\n").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidHex("start"));
+
+        let err = MapFile::parse(".text section layout\n80003100 000030 80003100 x memset\n").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidAlign);
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(placeholder_addr("zz_80003100_"), Some(0x80003100));
+        assert_eq!(placeholder_addr("FUN_80003100"), Some(0x80003100));
+        assert_eq!(placeholder_addr("fn_8000310"), None);
+        assert!(!is_placeholder("memset"));
+        assert_eq!(placeholder_name(0x80003100), "zz_80003100_");
+    }
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("_memset2"));
+        assert!(!is_identifier("2memset"));
+        assert!(!is_identifier("mem set"));
+        assert!(!is_identifier(""));
+    }
+
+    #[test]
+    fn insert_sorted_keeps_order() {
+        let mut mapfile = MapFile::parse(SAMPLE).unwrap();
+        let text = mapfile.section_mut(".text").unwrap();
+        text.insert_sorted(MapEntry { start: 0x80003110, size: 0, vaddr: 0x80003110, align: 0, name: "a".into() });
+        let starts = text.entries.iter().map(|e| e.start).collect::<Vec<_>>();
+        assert_eq!(starts, [0x80003100, 0x80003110, 0x80003130]);
+    }
+}
@@ -0,0 +1,303 @@
+//! Three-way merging of maps, e.g. as a git merge driver.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::map::{self, MapEntry, MapFile, Section};
+
+/// Result of a three-way merge. Entries that could not be merged are kept as conflicts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeResult {
+    /// Sections in the order of ours, then any new sections in theirs.
+    pub sections: Vec<MergedSection>,
+}
+
+/// A section of the merged map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergedSection {
+    /// Section name, e.g. ".text".
+    pub name: String,
+    /// Merged entries and conflicts, in address order.
+    pub items: Vec<MergeItem>,
+}
+
+/// One entry of the merged map, or a conflict in its place.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeItem {
+    /// An entry both sides agree on, or that only one side changed.
+    Entry(MapEntry),
+
+    /// Both sides changed the entry at this address in different ways.
+    /// `None` means that side removed the entry.
+    Conflict {
+        /// The entry in our map.
+        ours: Option<MapEntry>,
+        /// The entry in their map.
+        theirs: Option<MapEntry>,
+    },
+}
+
+impl MergeResult {
+    /// Number of conflicts in all sections.
+    pub fn conflicts(&self) -> usize {
+        self.sections.iter()
+            .flat_map(|s| s.items.iter())
+            .filter(|item| matches!(item, MergeItem::Conflict { .. }))
+            .count()
+    }
+
+    /// Returns the merged map, or `None` if there are conflicts.
+    pub fn into_mapfile(self) -> Option<MapFile> {
+        let mut sections = Vec::with_capacity(self.sections.len());
+
+        for section in self.sections {
+            let mut entries = Vec::with_capacity(section.items.len());
+            for item in section.items {
+                match item {
+                    MergeItem::Entry(entry) => entries.push(entry),
+                    MergeItem::Conflict { .. } => return None,
+                }
+            }
+            sections.push(Section { name: section.name, entries });
+        }
+
+        Some(MapFile { sections })
+    }
+}
+
+/// Writes the merged map in Dolphin's format, with git style conflict markers around conflicts.
+impl fmt::Display for MergeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, section) in self.sections.iter().enumerate() {
+            if i != 0 { writeln!(f)?; }
+            writeln!(f, "{}{}", section.name, map::SECTION_HEADER_SUFFIX)?;
+
+            for item in section.items.iter() {
+                match item {
+                    MergeItem::Entry(entry) => writeln!(f, "{}", entry)?,
+                    MergeItem::Conflict { ours, theirs } => {
+                        writeln!(f, "<<<<<<< ours")?;
+                        if let Some(entry) = ours { writeln!(f, "{}", entry)?; }
+                        writeln!(f, "=======")?;
+                        if let Some(entry) = theirs { writeln!(f, "{}", entry)?; }
+                        writeln!(f, ">>>>>>> theirs")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Merges `ours` and `theirs`, which were both derived from `base`.
+///
+/// Entries are matched by section and start address, and each column is merged
+/// separately. A column only conflicts if both sides changed it to different values,
+/// so renames and resizes of different entries never conflict.
+///
+/// A name that the merge would give to several addresses, without either side having
+/// done so, is a conflict at each of them.
+///
+/// Entries sharing a start address, as Dolphin sometimes writes, are matched in map order.
+pub fn merge(base: &MapFile, ours: &MapFile, theirs: &MapFile) -> MergeResult {
+    // sections in the order of ours, then any new sections in theirs
+    let mut section_names = Vec::<&str>::new();
+    for mapfile in [ours, theirs, base] {
+        for section in mapfile.sections.iter() {
+            if !section_names.contains(&section.name.as_str()) {
+                section_names.push(&section.name);
+            }
+        }
+    }
+
+    let mut result = MergeResult::default();
+    // the key of each item, to find the entries it came from
+    let mut item_keys = Vec::<Vec<EntryKey>>::new();
+
+    for name in section_names {
+        let base_entries = section_by_addr(base, name);
+        let our_entries = section_by_addr(ours, name);
+        let their_entries = section_by_addr(theirs, name);
+
+        let mut keys = base_entries.keys()
+            .chain(our_entries.keys())
+            .chain(their_entries.keys())
+            .copied()
+            .collect::<Vec<EntryKey>>();
+        keys.sort_unstable();
+        keys.dedup();
+
+        let mut items = Vec::new();
+        let mut section_keys = Vec::new();
+        for key in keys {
+            let b = base_entries.get(&key).copied();
+            let o = our_entries.get(&key).copied();
+            let t = their_entries.get(&key).copied();
+
+            match merge_entry(b, o, t) {
+                Some(Some(entry)) => items.push(MergeItem::Entry(entry)),
+                Some(None) => continue, // removed
+                None => items.push(MergeItem::Conflict { ours: o.cloned(), theirs: t.cloned() }),
+            }
+            section_keys.push(key);
+        }
+
+        result.sections.push(MergedSection { name: name.to_string(), items });
+        item_keys.push(section_keys);
+    }
+
+    // e.g. both sides gave the same name to different entries
+    let merged_names = name_counts(result.sections.iter().flat_map(|s| s.items.iter()).filter_map(|item| match item {
+        MergeItem::Entry(entry) => Some(entry),
+        MergeItem::Conflict { .. } => None,
+    }));
+    let our_names = name_counts(ours.entries());
+    let their_names = name_counts(theirs.entries());
+    let is_new_duplicate = |name: &str| {
+        merged_names[name] > 1 && our_names.get(name).is_none_or(|&n| n <= 1) && their_names.get(name).is_none_or(|&n| n <= 1)
+    };
+    let duplicates = merged_names.keys().filter(|name| is_new_duplicate(name)).map(|name| name.to_string()).collect::<Vec<_>>();
+
+    for (section, keys) in result.sections.iter_mut().zip(item_keys.iter()) {
+        for (item, key) in section.items.iter_mut().zip(keys.iter()) {
+            let MergeItem::Entry(entry) = item else { continue };
+            if !duplicates.contains(&entry.name) { continue }
+
+            let side = |mapfile: &MapFile| section_by_addr(mapfile, &section.name).get(key).map(|&e| e.clone());
+            *item = MergeItem::Conflict { ours: side(ours), theirs: side(theirs) };
+        }
+    }
+
+    result
+}
+
+fn name_counts<'a>(entries: impl Iterator<Item=&'a MapEntry>) -> HashMap<&'a str, usize> {
+    let mut counts = HashMap::new();
+    for entry in entries {
+        *counts.entry(entry.name.as_str()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Start address, and how many entries before this one in the section start there too.
+type EntryKey = (u32, usize);
+
+fn section_by_addr<'a>(mapfile: &'a MapFile, name: &str) -> BTreeMap<EntryKey, &'a MapEntry> {
+    let mut entries = BTreeMap::new();
+    if let Some(section) = mapfile.section(name) {
+        for entry in section.entries.iter() {
+            let n = entries.range((entry.start, 0)..=(entry.start, usize::MAX)).count();
+            entries.insert((entry.start, n), entry);
+        }
+    }
+    entries
+}
+
+/// Returns `None` on conflict, `Some(None)` if the entry was removed.
+fn merge_entry(
+    base: Option<&MapEntry>,
+    ours: Option<&MapEntry>,
+    theirs: Option<&MapEntry>,
+) -> Option<Option<MapEntry>> {
+    if ours == theirs { return Some(ours.cloned()) }
+    if base == ours { return Some(theirs.cloned()) }
+    if base == theirs { return Some(ours.cloned()) }
+
+    // both sides changed the entry differently, try each column separately
+    let (Some(b), Some(o), Some(t)) = (base, ours, theirs) else { return None };
+
+    Some(Some(MapEntry {
+        start: b.start,
+        size: merge_column(&b.size, &o.size, &t.size)?,
+        vaddr: merge_column(&b.vaddr, &o.vaddr, &t.vaddr)?,
+        align: merge_column(&b.align, &o.align, &t.align)?,
+        name: merge_column(&b.name, &o.name, &t.name)?,
+    }))
+}
+
+fn merge_column<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || base == theirs {
+        Some(ours.clone())
+    } else if base == ours {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "\
+.text section layout
+80005340 00001c 80005340 0 zz_80005340_
+8000535c 0000c0 8000535c 0 zz_8000535c_
+8000541c 000020 8000541c 0 zz_8000541c_
+";
+
+    fn merge_str(ours: &str, theirs: &str) -> MergeResult {
+        let parse = |src| MapFile::parse(src).unwrap();
+        merge(&parse(BASE), &parse(ours), &parse(theirs))
+    }
+
+    #[test]
+    fn merges_changes_to_different_entries() {
+        let ours = BASE.replace("0 zz_80005340_", "0 Foo");
+        let theirs = BASE.replace("0000c0 8000535c 0 zz_8000535c_", "0000c4 8000535c 0 zz_8000535c_");
+        let result = merge_str(&ours, &theirs);
+        assert_eq!(result.conflicts(), 0);
+
+        let expected = BASE.replace("0 zz_80005340_", "0 Foo").replace("0000c0 8000535c", "0000c4 8000535c");
+        assert_eq!(result.into_mapfile().unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn merges_columns_of_one_entry() {
+        let ours = BASE.replace("0 zz_80005340_", "0 Foo");
+        let theirs = BASE.replace("00001c 80005340", "000020 80005340");
+        let merged = merge_str(&ours, &theirs).into_mapfile().unwrap();
+        assert_eq!(merged.entry_at(0x80005340).unwrap().1.to_string(), "80005340 000020 80005340 0 Foo");
+    }
+
+    #[test]
+    fn conflicting_renames() {
+        let ours = BASE.replace("0 zz_80005340_", "0 Foo");
+        let theirs = BASE.replace("0 zz_80005340_", "0 Bar");
+        let result = merge_str(&ours, &theirs);
+        assert_eq!(result.conflicts(), 1);
+        assert!(result.to_string().contains("<<<<<<< ours\n80005340 00001c 80005340 0 Foo\n=======\n80005340 00001c 80005340 0 Bar\n>>>>>>> theirs\n"));
+        assert_eq!(result.into_mapfile(), None);
+    }
+
+    #[test]
+    fn same_name_at_two_addresses_conflicts() {
+        let ours = BASE.replace("0 zz_80005340_", "0 Foo");
+        let theirs = BASE.replace("0 zz_8000535c_", "0 Foo");
+        let result = merge_str(&ours, &theirs);
+        assert_eq!(result.conflicts(), 2);
+        assert_eq!(result.sections[0].items[0], MergeItem::Conflict {
+            ours: MapEntry::parse("80005340 00001c 80005340 0 Foo").ok(),
+            theirs: MapEntry::parse("80005340 00001c 80005340 0 zz_80005340_").ok(),
+        });
+    }
+
+    #[test]
+    fn entries_sharing_a_start() {
+        let base = BASE.to_string() + "8000541c 000000 8000541c 0 Empty\n";
+        let ours = base.replace("0 Empty", "0 Bar");
+        let theirs = base.replace("0 zz_80005340_", "0 Foo");
+        let parse = |src: &str| MapFile::parse(src).unwrap();
+
+        let merged = merge(&parse(&base), &parse(&ours), &parse(&theirs)).into_mapfile().unwrap();
+        let expected = base.replace("0 Empty", "0 Bar").replace("0 zz_80005340_", "0 Foo");
+        assert_eq!(merged.to_string(), expected);
+    }
+
+    #[test]
+    fn removed_entries() {
+        let ours = BASE.replace("8000541c 000020 8000541c 0 zz_8000541c_\n", "");
+        let merged = merge_str(&ours, BASE).into_mapfile().unwrap();
+        assert_eq!(merged.entry_at(0x8000541c), None);
+    }
+}
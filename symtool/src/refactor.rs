@@ -0,0 +1,168 @@
+//! Carrying map renames over to C source.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::journal::Batch;
+use crate::map;
+
+// Carrying map renames over to C source, e.g. after
+//
+//     symtool update GALE01.map < names.txt
+//     symtool refactor -j GALE01.map src/
+//
+// every whole identifier `zz_8006b0e8_` in src/ becomes the new name. Renames are applied
+// simultaneously, so swapping two names works.
+
+/// Old name -> new name.
+pub type Renames = HashMap<String, String>;
+
+/// The result of rewriting one source file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rewrite {
+    /// The rewritten source.
+    pub src: String,
+    /// Replacements made per old name.
+    pub counts: BTreeMap<String, usize>,
+}
+
+impl Rewrite {
+    /// Number of replacements of all names.
+    pub fn replacements(&self) -> usize {
+        self.counts.values().sum()
+    }
+}
+
+/// Parses an `old -> new` line, as printed by `symtool update` and `symtool rename`.
+/// Anything after the new name, such as the section, is ignored.
+pub fn parse_rename_line(line: &str) -> Option<(String, String)> {
+    let (old, rest) = line.split_once("->")?;
+    let old = old.trim();
+    let new = rest.split_whitespace().next()?;
+    if !map::is_identifier(old) || !map::is_identifier(new) { return None }
+    Some((old.to_string(), new.to_string()))
+}
+
+/// Returns the renames made by a journal batch.
+pub fn batch_renames(batch: &Batch) -> Renames {
+    let mut renames = Renames::new();
+    for change in batch.changes.iter() {
+        let (Some(old), Some(new)) = (&change.old, &change.new) else { continue };
+        if old.name != new.name {
+            renames.insert(old.name.clone(), new.name.clone());
+        }
+    }
+    renames
+}
+
+/// Replaces every whole identifier in `src` that is a key of `renames`.
+///
+/// If `code_only` is set, comments, string literals and character literals are left alone.
+pub fn rewrite(src: &str, renames: &Renames, code_only: bool) -> Rewrite {
+    let mut rewrite = Rewrite { src: String::with_capacity(src.len()), counts: BTreeMap::new() };
+    let bytes = src.as_bytes();
+
+    // src[copied..i] is yet to be written
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &src[i..];
+        let c = bytes[i];
+
+        if code_only {
+            let skip = if rest.starts_with("//") {
+                rest.find('\n').unwrap_or(rest.len())
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                comment.find("*/").map_or(rest.len(), |end| end + 4)
+            } else if c == b'"' || c == b'\'' {
+                literal_len(rest)
+            } else {
+                0
+            };
+
+            if skip != 0 {
+                i += skip;
+                continue;
+            }
+        }
+
+        if c.is_ascii_alphanumeric() || c == b'_' {
+            // numbers like 0x1f are skipped whole, so their digits aren't mistaken for identifiers
+            let len = rest.bytes().position(|b| !b.is_ascii_alphanumeric() && b != b'_').unwrap_or(rest.len());
+            let token = &rest[..len];
+
+            if !c.is_ascii_digit() && let Some(new) = renames.get(token) {
+                rewrite.src.push_str(&src[copied..i]);
+                rewrite.src.push_str(new);
+                *rewrite.counts.entry(token.to_string()).or_default() += 1;
+                copied = i + len;
+            }
+
+            i += len;
+            continue;
+        }
+
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+
+    rewrite.src.push_str(&src[copied..]);
+    rewrite
+}
+
+/// Length of the string or character literal at the start of `src`, including its quotes.
+/// Unterminated literals end at the end of the line.
+fn literal_len(src: &str) -> usize {
+    let quote = src.as_bytes()[0];
+    let mut escaped = false;
+
+    for (i, b) in src.bytes().enumerate().skip(1) {
+        match b {
+            b'\n' => return i,
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            _ if b == quote => return i + 1,
+            _ => {}
+        }
+    }
+
+    src.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renames(pairs: &[(&str, &str)]) -> Renames {
+        pairs.iter().map(|&(old, new)| (old.to_string(), new.to_string())).collect()
+    }
+
+    #[test]
+    fn whole_identifiers() {
+        let renames = renames(&[("zz_8006b0e8_", "Player_GetFacing")]);
+        let rewrite = rewrite("f(zz_8006b0e8_); zz_8006b0e8_x = 1; xzz_8006b0e8_();", &renames, false);
+        assert_eq!(rewrite.src, "f(Player_GetFacing); zz_8006b0e8_x = 1; xzz_8006b0e8_();");
+        assert_eq!(rewrite.replacements(), 1);
+    }
+
+    #[test]
+    fn swap() {
+        let renames = renames(&[("a", "b"), ("b", "a")]);
+        let rewrite = rewrite("a(b);", &renames, false);
+        assert_eq!(rewrite.src, "b(a);");
+        assert_eq!(rewrite.counts, BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 1)]));
+    }
+
+    #[test]
+    fn code_only() {
+        let renames = renames(&[("foo", "bar")]);
+        let src = "foo(); // foo\n/* foo */ s = \"foo \\\" foo\"; c = 'f'; foo;\n";
+        assert_eq!(rewrite(src, &renames, true).src, "bar(); // foo\n/* foo */ s = \"foo \\\" foo\"; c = 'f'; bar;\n");
+        assert_eq!(rewrite(src, &renames, false).replacements(), 6);
+    }
+
+    #[test]
+    fn rename_lines() {
+        assert_eq!(parse_rename_line("zz_80005340_ -> Foo .text"), Some(("zz_80005340_".into(), "Foo".into())));
+        assert_eq!(parse_rename_line("skipped 1 conflicting updates:"), None);
+        assert_eq!(parse_rename_line("a -> not-valid"), None);
+    }
+}
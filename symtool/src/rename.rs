@@ -0,0 +1,205 @@
+//! Batch renaming map entries by regex.
+
+use std::ops::Range;
+
+use regex_lite::{Captures, Regex};
+
+use crate::map::{MapEntry, MapFile};
+use crate::update::UpdateRecord;
+
+// Batch renames by regex, e.g. turning Dolphin placeholders into decomp-toolkit ones:
+//
+//     symtool rename -e '^zz_([0-9a-f]{8})_$' 'fn_$1' GALE01.map
+//
+// Rules produce update records, so renames go through the same checks as `update`.
+
+/// A search and replace rule for entry names.
+///
+/// The replacement may refer to capture groups as `$1` or `${name}`, and to the
+/// entry's address as `{addr}`, written as 8 lowercase hex digits. Unlike in regex,
+/// `$1_x` is group 1 followed by `_x`.
+#[derive(Clone, Debug)]
+pub struct RenameRule {
+    /// Matched against whole names or parts of them.
+    pub pattern: Regex,
+    /// Replacement of each match, with group numbers in braces, see [`RenameRule::new`].
+    pub replacement: String,
+}
+
+/// Limits which entries rules apply to. Unset fields match every entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scope {
+    /// Start addresses, end exclusive.
+    pub range: Option<Range<u32>>,
+    /// Section name, e.g. ".text".
+    pub section: Option<String>,
+    /// Name glob, see [`glob_match`].
+    pub glob: Option<String>,
+}
+
+impl RenameRule {
+    /// Compiles the pattern. `$1` in the replacement is stored as `${1}`.
+    pub fn new(pattern: &str, replacement: &str) -> Result<RenameRule, regex_lite::Error> {
+        Ok(RenameRule { pattern: Regex::new(pattern)?, replacement: brace_group_numbers(replacement) })
+    }
+
+    /// Returns the new name, or `None` if the pattern doesn't match.
+    pub fn apply(&self, name: &str, addr: u32) -> Option<String> {
+        if !self.pattern.is_match(name) { return None }
+
+        // expand captures first, so the address can't run into a '$1' before it.
+        // '${addr}' is a capture group named addr, which expand has already replaced
+        let addr = format!("{:08x}", addr);
+        let new = self.pattern.replace_all(name, |caps: &Captures| {
+            let mut expanded = String::new();
+            caps.expand(&self.replacement, &mut expanded);
+            expanded.replace("{addr}", &addr)
+        });
+        Some(new.into_owned())
+    }
+}
+
+/// Rewrites `$1` as `${1}`, so a group number ends at its last digit. regex would read
+/// `$1_foo` as the group named "1_foo", which is never what a symbol name means.
+fn brace_group_numbers(replacement: &str) -> String {
+    let mut out = String::with_capacity(replacement.len());
+    let mut rest = replacement;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i+1..];
+
+        // '$$' is a literal '$'
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push_str("$$");
+            rest = after;
+            continue;
+        }
+
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            out.push('$');
+        } else {
+            out.push_str(&format!("${{{}}}", &rest[..digits]));
+            rest = &rest[digits..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+impl Scope {
+    /// Whether rules apply to `entry` in the section named `section`.
+    pub fn contains(&self, section: &str, entry: &MapEntry) -> bool {
+        self.range.as_ref().is_none_or(|r| r.contains(&entry.start))
+            && self.section.as_ref().is_none_or(|s| s == section)
+            && self.glob.as_ref().is_none_or(|g| glob_match(g, &entry.name))
+    }
+}
+
+/// Whether `name` matches `glob`, where `*` matches any run of characters and `?` any one character.
+pub fn glob_match(glob: &str, name: &str) -> bool {
+    let glob = glob.as_bytes();
+    let name = name.as_bytes();
+
+    // backtrack to the last '*' on mismatch
+    let (mut g, mut n) = (0, 0);
+    let mut star = None::<(usize, usize)>;
+    while n < name.len() {
+        if g < glob.len() && (glob[g] == b'?' || glob[g] == name[n]) {
+            g += 1;
+            n += 1;
+        } else if g < glob.len() && glob[g] == b'*' {
+            star = Some((g, n));
+            g += 1;
+        } else if let Some((sg, sn)) = star {
+            g = sg + 1;
+            n = sn + 1;
+            star = Some((sg, sn + 1));
+        } else {
+            return false;
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == b'*')
+}
+
+/// Applies `rules` in order to the name of every entry in `scope`, each rule to the
+/// result of the previous one. Returns an update record for each entry whose name changed.
+pub fn records(mapfile: &MapFile, rules: &[RenameRule], scope: &Scope) -> Vec<UpdateRecord> {
+    let mut records = Vec::new();
+
+    for (section, entry) in mapfile.section_entries() {
+        if !scope.contains(&section.name, entry) { continue }
+
+        let mut name = entry.name.clone();
+        for rule in rules {
+            if let Some(new) = rule.apply(&name, entry.start) { name = new; }
+        }
+        if name == entry.name { continue }
+
+        records.push(UpdateRecord {
+            addr: entry.start,
+            name,
+            section: Some(section.name.clone()),
+            size: None,
+            vaddr: None,
+            align: None,
+        });
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_and_addr() {
+        let rule = RenameRule::new("^zz_([0-9a-f]{8})_$", "fn_$1").unwrap();
+        assert_eq!(rule.apply("zz_80005340_", 0x80005340).as_deref(), Some("fn_80005340"));
+        assert_eq!(rule.apply("memset", 0x80003100), None);
+
+        let rule = RenameRule::new("^(zz)_.*$", "x_$1_{addr}").unwrap();
+        assert_eq!(rule.apply("zz_80005340_", 0x80005340).as_deref(), Some("x_zz_80005340"));
+
+        let rule = RenameRule::new("^(zz)_.*$", "$1{addr}").unwrap();
+        assert_eq!(rule.apply("zz_80005340_", 0x80005340).as_deref(), Some("zz80005340"));
+
+        let rule = RenameRule::new("^(?P<kind>[a-z]+)_([0-9a-f]{8})_$", "${kind}_$2_$$").unwrap();
+        assert_eq!(rule.apply("zz_80005340_", 0x80005340).as_deref(), Some("zz_80005340_$"));
+
+        let rule = RenameRule::new("^lbl_(?P<addr>.*)$", "data_${addr}").unwrap();
+        assert_eq!(rule.apply("lbl_X", 0x803b7240).as_deref(), Some("data_X"));
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("HSD_*", "HSD_JObjSetMtxDirty"));
+        assert!(glob_match("*Mtx*", "HSD_JObjSetMtxDirty"));
+        assert!(glob_match("zz_????????_", "zz_80005340_"));
+        assert!(!glob_match("HSD_*", "GX_Begin"));
+        assert!(!glob_match("zz_?", "zz_80"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn scoped_records() {
+        let mapfile = MapFile::parse("\
+.text section layout
+80005340 00001c 80005340 0 zz_80005340_
+8000535c 0000c0 8000535c 0 zz_8000535c_
+80005400 000010 80005400 0 memset
+
+.data section layout
+803b7240 000008 803b7240 0 zz_803b7240_
+").unwrap();
+        let rules = [RenameRule::new("^zz_", "fn_").unwrap(), RenameRule::new("_$", "").unwrap()];
+        let scope = Scope { range: Some(0x80005340..0x80005400), section: Some(".text".into()), glob: None };
+
+        let records = records(&mapfile, &rules, &scope);
+        let names = records.iter().map(|r| (r.addr, r.name.as_str())).collect::<Vec<_>>();
+        assert_eq!(names, [(0x80005340, "fn_80005340"), (0x8000535c, "fn_8000535c")]);
+        assert_eq!(records[0].section.as_deref(), Some(".text"));
+    }
+}
@@ -0,0 +1,281 @@
+//! Naming progress reports.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+
+use crate::json;
+use crate::map::{self, MapEntry, MapFile};
+
+/// Naming progress of a group of map entries.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Number of entries.
+    pub entries: usize,
+    /// Entries with a real name rather than a placeholder.
+    pub named: usize,
+    /// Total size of the entries.
+    pub bytes: u64,
+    /// Total size of the named entries.
+    pub named_bytes: u64,
+}
+
+/// Naming progress of a whole map, see [`stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MapStats {
+    /// All entries.
+    pub total: Coverage,
+    /// Entries by section, in map order.
+    pub sections: Vec<(String, Coverage)>,
+    /// Named entries grouped by name prefix, most common first. See [`name_prefix`].
+    pub prefixes: Vec<(String, Coverage)>,
+    /// Entries grouped by `region_size` aligned address region, in address order.
+    pub regions: Vec<(u32, Coverage)>,
+    /// Size of the address regions in bytes.
+    pub region_size: u32,
+}
+
+impl Coverage {
+    /// Counts one more entry.
+    pub fn add(&mut self, entry: &MapEntry) {
+        self.entries += 1;
+        self.bytes += entry.size as u64;
+        if !map::is_placeholder(&entry.name) {
+            self.named += 1;
+            self.named_bytes += entry.size as u64;
+        }
+    }
+
+    /// Entries with a placeholder name.
+    pub fn placeholders(&self) -> usize {
+        self.entries - self.named
+    }
+}
+
+/// Returns the prefix of a name up to and including its first underscore,
+/// e.g. "HSD_" for "HSD_JObjSetMtxDirty". Names without a short prefix return `None`.
+pub fn name_prefix(name: &str) -> Option<&str> {
+    const MAX_PREFIX_LEN: usize = 8;
+
+    let i = name.find('_')?;
+    if i == 0 || i > MAX_PREFIX_LEN { return None }
+    Some(&name[..=i])
+}
+
+/// Computes naming progress over the whole map, per section, per name prefix
+/// and per address region of `region_size` bytes. A `region_size` of 0 is taken as 1.
+pub fn stats(mapfile: &MapFile, region_size: u32) -> MapStats {
+    let region_size = region_size.max(1);
+    let mut stats = MapStats { region_size, ..MapStats::default() };
+
+    let mut prefixes = HashMap::<&str, Coverage>::new();
+    let mut regions = BTreeMap::<u32, Coverage>::new();
+
+    for section in mapfile.sections.iter() {
+        let mut coverage = Coverage::default();
+
+        for entry in section.entries.iter() {
+            coverage.add(entry);
+            stats.total.add(entry);
+            regions.entry(entry.start - entry.start % region_size).or_default().add(entry);
+
+            if map::is_placeholder(&entry.name) { continue }
+            let prefix = name_prefix(&entry.name).unwrap_or("(none)");
+            prefixes.entry(prefix).or_default().add(entry);
+        }
+
+        stats.sections.push((section.name.clone(), coverage));
+    }
+
+    stats.prefixes = prefixes.into_iter().map(|(p, c)| (p.to_string(), c)).collect();
+    stats.prefixes.sort_by(|(a_name, a), (b_name, b)| b.entries.cmp(&a.entries).then(a_name.cmp(b_name)));
+    stats.regions = regions.into_iter().collect();
+
+    stats
+}
+
+impl MapStats {
+    /// Writes the section, prefix and region tables as aligned plain text.
+    pub fn write_text(&self, out: &mut impl Write) -> fmt::Result {
+        self.write_tables(out, Table::write_text)
+    }
+
+    /// Writes the section, prefix and region tables as markdown tables.
+    pub fn write_markdown(&self, out: &mut impl Write) -> fmt::Result {
+        self.write_tables(out, Table::write_markdown)
+    }
+
+    fn write_tables<W: Write>(&self, out: &mut W, write_table: fn(&Table, &mut W) -> fmt::Result) -> fmt::Result {
+        let mut table = Table::new(&["section", "entries", "named", "placeholders", "named %", "bytes", "named bytes %"]);
+        for (name, c) in self.sections.iter() {
+            table.push_coverage(name.clone(), c);
+        }
+        table.push_coverage("total".to_string(), &self.total);
+        write_table(&table, out)?;
+        writeln!(out)?;
+
+        let mut table = Table::new(&["prefix", "named", "bytes"]);
+        for (prefix, c) in self.prefixes.iter() {
+            table.rows.push(vec![prefix.clone(), c.named.to_string(), c.named_bytes.to_string()]);
+        }
+        write_table(&table, out)?;
+        writeln!(out)?;
+
+        let mut table = Table::new(&["region", "entries", "named", "placeholders", "named %", "bytes", "named bytes %"]);
+        for (start, c) in self.regions.iter() {
+            let end = start.saturating_add(self.region_size);
+            table.push_coverage(format!("{:08x}-{:08x}", start, end), c);
+        }
+        write_table(&table, out)
+    }
+
+    /// Writes the stats as a JSON object.
+    pub fn write_json(&self, out: &mut impl Write) -> fmt::Result {
+        let write_coverage = |out: &mut dyn Write, c: &Coverage| write!(
+            out,
+            "\"entries\": {}, \"named\": {}, \"placeholders\": {}, \"bytes\": {}, \"named_bytes\": {}",
+            c.entries, c.named, c.placeholders(), c.bytes, c.named_bytes,
+        );
+
+        out.write_str("{\n    \"total\": {")?;
+        write_coverage(out, &self.total)?;
+        out.write_str("},\n    \"sections\": [")?;
+        for (i, (name, c)) in self.sections.iter().enumerate() {
+            out.write_str(if i == 0 { "\n        {\"section\": " } else { ",\n        {\"section\": " })?;
+            json::write_str(out, name)?;
+            out.write_str(", ")?;
+            write_coverage(out, c)?;
+            out.write_char('}')?;
+        }
+        out.write_str("\n    ],\n    \"prefixes\": [")?;
+        for (i, (prefix, c)) in self.prefixes.iter().enumerate() {
+            out.write_str(if i == 0 { "\n        {\"prefix\": " } else { ",\n        {\"prefix\": " })?;
+            json::write_str(out, prefix)?;
+            write!(out, ", \"named\": {}, \"bytes\": {}}}", c.named, c.named_bytes)?;
+        }
+        write!(out, "\n    ],\n    \"region_size\": {},\n    \"regions\": [", self.region_size)?;
+        for (i, (start, c)) in self.regions.iter().enumerate() {
+            out.write_str(if i == 0 { "\n        " } else { ",\n        " })?;
+            write!(out, "{{\"start\": \"0x{:08x}\", ", start)?;
+            write_coverage(out, c)?;
+            out.write_char('}')?;
+        }
+        out.write_str("\n    ]\n}\n")
+    }
+}
+
+struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new(header: &[&str]) -> Table {
+        Table { header: header.iter().map(|s| s.to_string()).collect(), rows: Vec::new() }
+    }
+
+    fn push_coverage(&mut self, label: String, c: &Coverage) {
+        self.rows.push(vec![
+            label,
+            c.entries.to_string(),
+            c.named.to_string(),
+            c.placeholders().to_string(),
+            percent(c.named as u64, c.entries as u64),
+            c.bytes.to_string(),
+            percent(c.named_bytes, c.bytes),
+        ]);
+    }
+
+    fn column_widths(&self) -> Vec<usize> {
+        let mut widths = self.header.iter().map(|h| h.len()).collect::<Vec<_>>();
+        for row in self.rows.iter() {
+            for (w, cell) in widths.iter_mut().zip(row.iter()) {
+                *w = (*w).max(cell.len());
+            }
+        }
+        widths
+    }
+
+    fn write_text(&self, out: &mut impl Write) -> fmt::Result {
+        let widths = self.column_widths();
+        for row in std::iter::once(&self.header).chain(self.rows.iter()) {
+            for (i, (cell, w)) in row.iter().zip(widths.iter()).enumerate() {
+                if i == 0 {
+                    write!(out, "{:<w$}", cell, w = w)?;
+                } else {
+                    write!(out, "  {:>w$}", cell, w = w)?;
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    fn write_markdown(&self, out: &mut impl Write) -> fmt::Result {
+        writeln!(out, "| {} |", self.header.join(" | "))?;
+        for i in 0..self.header.len() {
+            out.write_str(if i == 0 { "|---" } else { "|---:" })?;
+        }
+        writeln!(out, "|")?;
+        for row in self.rows.iter() {
+            writeln!(out, "| {} |", row.join(" | "))?;
+        }
+        Ok(())
+    }
+}
+
+fn percent(n: u64, total: u64) -> String {
+    if total == 0 { return "-".to_string() }
+    format!("{:.1}%", n as f64 * 100.0 / total as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80003130 0000c4 80003130 0 zz_80003130_
+80043100 000010 80043100 0 HSD_JObjSetMtxDirty
+
+.data section layout
+803b7240 000008 803b7240 0 HSD_table
+";
+
+    #[test]
+    fn coverage() {
+        let stats = stats(&MapFile::parse(MAP).unwrap(), 0x40000);
+        assert_eq!(stats.total, Coverage { entries: 4, named: 3, bytes: 0x30 + 0xc4 + 0x10 + 8, named_bytes: 0x30 + 0x10 + 8 });
+        assert_eq!(stats.sections[0].1.placeholders(), 1);
+        assert_eq!(stats.sections[1], (".data".to_string(), Coverage { entries: 1, named: 1, bytes: 8, named_bytes: 8 }));
+
+        let prefixes = stats.prefixes.iter().map(|(p, c)| (p.as_str(), c.named)).collect::<Vec<_>>();
+        assert_eq!(prefixes, [("HSD_", 2), ("(none)", 1)]);
+
+        let regions = stats.regions.iter().map(|(start, c)| (*start, c.entries)).collect::<Vec<_>>();
+        assert_eq!(regions, [(0x80000000, 2), (0x80040000, 1), (0x80380000, 1)]);
+    }
+
+    #[test]
+    fn zero_region_size() {
+        let stats = stats(&MapFile::parse(MAP).unwrap(), 0);
+        assert_eq!(stats.region_size, 1);
+        assert_eq!(stats.regions.len(), 4);
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(name_prefix("HSD_JObjSetMtxDirty"), Some("HSD_"));
+        assert_eq!(name_prefix("_start"), None);
+        assert_eq!(name_prefix("memset"), None);
+        assert_eq!(name_prefix("verylongprefix_x"), None);
+    }
+
+    #[test]
+    fn json_is_valid() {
+        let mut out = String::new();
+        stats(&MapFile::parse(MAP).unwrap(), 0x40000).write_json(&mut out).unwrap();
+        let value = json::parse(&out).unwrap();
+        assert_eq!(value.get("total").and_then(|t| t.get("named")).and_then(json::Value::as_u32), Some(3));
+    }
+}
@@ -0,0 +1,192 @@
+//! Crash safe writes of maps and their lock, backup and journal files.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::dtk::Split;
+use crate::history::NameChange;
+use crate::journal::Batch;
+
+// Safe on-disk editing of map files. Next to each edited map, e.g. GALE01.map, live:
+//
+//     GALE01.map.lock       advisory lock held while a command edits the map
+//     GALE01.map.bak        the map as it was before the last edit
+//     GALE01.map.journal    every edit, for `symtool undo`
+//     GALE01.map.history    who named each entry, when and why, for `symtool history`
+//     GALE01.map.objects    the object file of each address range, from `symtool import -s`
+//
+// Writes go through a temporary GALE01.map.<pid>-<n>.tmp, which is renamed over the map.
+
+/// An advisory lock on a map file, released when dropped.
+pub struct MapLock {
+    _file: File,
+}
+
+/// Path of a sidecar file of `map_path`, e.g. "GALE01.map.journal" for extension "journal".
+pub fn sidecar_path(map_path: &Path, extension: &str) -> PathBuf {
+    let mut path = map_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Blocks until no other process is editing the map at `map_path`.
+pub fn lock(map_path: &Path) -> io::Result<MapLock> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sidecar_path(map_path, "lock"))?;
+    file.lock()?;
+    Ok(MapLock { _file: file })
+}
+
+/// Writes `contents` to a temporary file next to `path`, then renames it over `path`,
+/// so `path` is never left partially written. The file keeps the permissions of the
+/// file it replaces.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    static TMP_COUNT: AtomicU32 = AtomicU32::new(0);
+
+    // unique per process and call, so concurrent writers never share a temporary file
+    let (tmp_path, mut file) = loop {
+        let n = TMP_COUNT.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = sidecar_path(path, &format!("{}-{}.tmp", std::process::id(), n));
+        match OpenOptions::new().write(true).create_new(true).open(&tmp_path) {
+            Ok(file) => break (tmp_path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    };
+
+    let res = (|| {
+        match std::fs::metadata(path) {
+            Ok(metadata) => file.set_permissions(metadata.permissions())?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+    })();
+
+    if res.is_err() { let _ = std::fs::remove_file(&tmp_path); }
+    res
+}
+
+/// Writes the previous contents of the map to its backup file.
+pub fn write_backup(map_path: &Path, old_contents: &[u8]) -> io::Result<()> {
+    write_atomic(&sidecar_path(map_path, "bak"), old_contents)
+}
+
+/// Adds a batch to the end of the journal of the map at `map_path`.
+pub fn append_journal(map_path: &Path, batch: &Batch) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(sidecar_path(map_path, "journal"))?;
+    file.write_all(batch.to_string().as_bytes())?;
+    file.sync_all()
+}
+
+/// Reads the journal of the map at `map_path`. A missing journal is empty.
+pub fn read_journal(map_path: &Path) -> io::Result<String> {
+    match std::fs::read_to_string(sidecar_path(map_path, "journal")) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        res => res,
+    }
+}
+
+/// Replaces the journal of the map at `map_path` with `batches`.
+pub fn write_journal(map_path: &Path, batches: &[Batch]) -> io::Result<()> {
+    let contents = batches.iter().map(|b| b.to_string()).collect::<String>();
+    write_atomic(&sidecar_path(map_path, "journal"), contents.as_bytes())
+}
+
+/// Adds name changes to the end of the history of the map at `map_path`.
+pub fn append_history(map_path: &Path, changes: &[NameChange]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(sidecar_path(map_path, "history"))?;
+    let contents = changes.iter().map(|c| c.to_string()).collect::<String>();
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()
+}
+
+/// Reads the history of the map at `map_path`. A missing history is empty.
+pub fn read_history(map_path: &Path) -> io::Result<String> {
+    match std::fs::read_to_string(sidecar_path(map_path, "history")) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        res => res,
+    }
+}
+
+/// Replaces the object file address ranges of the map at `map_path`.
+pub fn write_objects(map_path: &Path, splits: &[Split]) -> io::Result<()> {
+    let contents = splits.iter().map(|s| s.to_string()).collect::<String>();
+    write_atomic(&sidecar_path(map_path, "objects"), contents.as_bytes())
+}
+
+/// Reads the object file address ranges of the map at `map_path`. Missing ranges are empty.
+pub fn read_objects(map_path: &Path) -> io::Result<String> {
+    match std::fs::read_to_string(sidecar_path(map_path, "objects")) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        res => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("symtool-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn write_atomic_replaces_file() {
+        let dir = temp_dir("write-atomic");
+        let path = dir.join("GALE01.map");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+
+        // no temporary files are left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("permissions");
+        let path = dir.join("main.c");
+        std::fs::write(&path, b"old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o750);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sidecars() {
+        let dir = temp_dir("sidecars");
+        let path = dir.join("GALE01.map");
+        assert_eq!(sidecar_path(&path, "journal"), dir.join("GALE01.map.journal"));
+        assert_eq!(read_journal(&path).unwrap(), "");
+        assert_eq!(read_history(&path).unwrap(), "");
+        assert_eq!(read_objects(&path).unwrap(), "");
+
+        write_backup(&path, b"backup").unwrap();
+        assert_eq!(std::fs::read(sidecar_path(&path, "bak")).unwrap(), b"backup");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
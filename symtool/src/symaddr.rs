@@ -0,0 +1,126 @@
+//! Finding symbol and address pairs in free-form text.
+
+use std::ops::Range;
+
+/// A symbol and address found on the same line of free-form text.
+pub struct SymAddr<'a> {
+    /// The address.
+    pub addr: u32,
+    /// Byte range of the address within the line.
+    pub addr_range: Range<usize>,
+
+    /// The symbol name.
+    pub symbol: &'a str,
+    /// Byte range of the symbol within the line.
+    pub symbol_range: Range<usize>,
+}
+
+/// Finds the first 8 digit hex number in 0x80000000..0x81800000 on a line.
+///
+/// Returns the address and its byte range within the line.
+pub fn line_addr(line: &str) -> Option<(u32, Range<usize>)> {
+    'addr_window: for (i, addr_bytes) in line.as_bytes().windows(8).enumerate() {
+        let mut cur_addr = 0;
+        for b in addr_bytes {
+            let n = match b {
+                b'0'..=b'9' => (b - b'0') as u32,
+                b'a'..=b'f' => (b - b'a' + 10) as u32,
+                b'A'..=b'F' => (b - b'A' + 10) as u32,
+                _ => continue 'addr_window,
+            };
+            cur_addr = (cur_addr << 4) | n;
+        }
+        
+        if (0x80000000..0x81800000).contains(&cur_addr) {
+            return Some((cur_addr, i..i+8));
+        }
+    }
+    
+    None
+}
+
+/// Finds the first address in 0x80000000..0x81800000 and the first identifier on a line.
+///
+/// This accepts most formats that put a symbol and its address on the same line,
+/// such as Ghidra exports, `addr` output or Dolphin map entries.
+pub fn line_symaddr(line: &str) -> Option<SymAddr<'_>> {
+    // find address ----------------------------------
+    
+    let (addr, addr_range) = line_addr(line)?;
+    
+    // find symbol ----------------------------------
+    
+    let mut chars = line.char_indices();
+    
+    let start_i = 'find_start_i: loop {
+        loop {
+            match chars.next() {
+                // don't parse hex numbers as a symbol 
+                Some((_, c)) if c.is_numeric() => break,
+
+                Some((i, c)) if c.is_ascii_alphabetic() || c == '_' => break 'find_start_i i,
+                None => return None,
+                _ => {}
+            }
+        }
+        
+        // skip hex digits
+        loop {
+            match chars.next() {
+                Some((_, c)) if !c.is_ascii_hexdigit() => break,
+                None => return None,
+                _ => {}
+            }
+        }
+    };
+    
+    let end_i = loop {
+        match chars.next() {
+            Some((_, c)) if c.is_ascii_alphanumeric() || c == '_' => {},
+            Some((i, _)) => break i,
+            None => break chars.offset(),
+        }
+    };
+    
+    let symbol = &line[start_i..end_i];
+    
+    Some(SymAddr {
+        addr,
+        addr_range,
+        symbol,
+        symbol_range: start_i..end_i,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses() {
+        assert_eq!(line_addr("lookup 80003100 please"), Some((0x80003100, 7..15)));
+        assert_eq!(line_addr("0x803B7240,"), Some((0x803b7240, 2..10)));
+
+        // outside main memory, or part of a longer number
+        assert_eq!(line_addr("7fffffff 81800000"), None);
+        assert_eq!(line_addr("1280003100"), Some((0x80003100, 2..10)));
+        assert_eq!(line_addr("no address"), None);
+    }
+
+    #[test]
+    fn symbols_and_addresses() {
+        let info = line_symaddr("80003100 000030 80003100 0 memset").unwrap();
+        assert_eq!((info.addr, info.symbol), (0x80003100, "memset"));
+        assert_eq!(info.symbol_range, 27..33);
+
+        // Ghidra style, symbol first
+        let info = line_symaddr("memset\t0x80003100\tFunction").unwrap();
+        assert_eq!((info.addr, info.symbol), (0x80003100, "memset"));
+
+        // numbers are not symbols
+        let info = line_symaddr("80003100 000030 0x4c memset").unwrap();
+        assert_eq!(info.symbol, "memset");
+
+        assert!(line_symaddr("80003100 0030").is_none());
+    }
+}
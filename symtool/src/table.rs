@@ -0,0 +1,532 @@
+//! JSON, CSV and TSV tables of map entries.
+
+use std::fmt::{self, Write};
+
+use crate::check::{self, Problem, ProblemKind};
+use crate::dtk::Split;
+use crate::json::{self, Value};
+use crate::map::{self, MapEntry, MapFile, ParseError, ParseErrorKind, Section};
+
+// The map as data for web tools and spreadsheets, one row per entry. The schema is stable:
+//
+//     section      section name, e.g. ".text"
+//     start        start address, "0x80003100"
+//     size         size in bytes, a decimal number
+//     vaddr        virtual address, "0x80003100"
+//     align        alignment, a decimal number
+//     name         symbol name
+//     placeholder  whether the name is a placeholder, true or false
+//     object       object file from an imported splits.txt, empty or null if unknown
+//
+// JSON is an object holding the schema version, the section names in map order and the rows:
+//
+//     {
+//         "schema": 1,
+//         "sections": [".text", ".data"],
+//         "entries": [
+//             {"section": ".text", "start": "0x80003100", "size": 48, ...}
+//         ]
+//     }
+//
+// CSV and TSV start with a header row of the column names. A section without entries is a
+// row with only the section column, so empty sections survive a round trip.
+//
+// placeholder and object are derived, and ignored when reading rows back.
+//
+// An imported table replaces the whole map, so it is checked against the map first, see validate.
+
+/// Version of the schema above, written to JSON tables.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Column names, in the order CSV and TSV tables are written.
+pub const COLUMNS: &[&str] = &["section", "start", "size", "vaddr", "align", "name", "placeholder", "object"];
+
+/// Returns the object file containing `addr`.
+fn object_at(objects: &[Split], addr: u32) -> Option<&str> {
+    objects.iter().find(|s| s.contains(addr)).map(|s| s.object.as_str())
+}
+
+/// Writes the map as a JSON table, with object files from `objects`.
+pub fn write_json(mapfile: &MapFile, objects: &[Split], out: &mut impl Write) -> fmt::Result {
+    write!(out, "{{\n    \"schema\": {},\n    \"sections\": [", SCHEMA_VERSION)?;
+    for (i, section) in mapfile.sections.iter().enumerate() {
+        if i != 0 { out.write_str(", ")?; }
+        json::write_str(out, &section.name)?;
+    }
+    out.write_str("],\n    \"entries\": [")?;
+
+    for (i, (section, entry)) in mapfile.section_entries().enumerate() {
+        out.write_str(if i == 0 { "\n        {\"section\": " } else { ",\n        {\"section\": " })?;
+        json::write_str(out, &section.name)?;
+        write!(
+            out, ", \"start\": \"0x{:08x}\", \"size\": {}, \"vaddr\": \"0x{:08x}\", \"align\": {}, \"name\": ",
+            entry.start, entry.size, entry.vaddr, entry.align,
+        )?;
+        json::write_str(out, &entry.name)?;
+        write!(out, ", \"placeholder\": {}, \"object\": ", map::is_placeholder(&entry.name))?;
+        match object_at(objects, entry.start) {
+            Some(object) => json::write_str(out, object)?,
+            None => out.write_str("null")?,
+        }
+        out.write_char('}')?;
+    }
+
+    out.write_str("\n    ]\n}\n")
+}
+
+/// Writes CSV if `separator` is ',', or TSV if it is '\t'.
+pub fn write_separated(mapfile: &MapFile, objects: &[Split], separator: char, out: &mut impl Write) -> fmt::Result {
+    let write_row = |out: &mut dyn Write, row: &[&str]| {
+        for (i, field) in row.iter().enumerate() {
+            if i != 0 { out.write_char(separator)?; }
+            write_field(out, field, separator)?;
+        }
+        out.write_char('\n')
+    };
+
+    write_row(out, COLUMNS)?;
+
+    for section in mapfile.sections.iter() {
+        if section.entries.is_empty() {
+            write_row(out, &[&section.name])?;
+        }
+
+        for entry in section.entries.iter() {
+            write_row(out, &[
+                &section.name,
+                &format!("0x{:08x}", entry.start),
+                &entry.size.to_string(),
+                &format!("0x{:08x}", entry.vaddr),
+                &entry.align.to_string(),
+                &entry.name,
+                if map::is_placeholder(&entry.name) { "true" } else { "false" },
+                object_at(objects, entry.start).unwrap_or(""),
+            ])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Quotes CSV fields that need it. TSV fields are written as is, they never contain tabs.
+fn write_field(out: &mut dyn Write, field: &str, separator: char) -> fmt::Result {
+    let needs_quotes = separator == ',' && field.contains([',', '"', '\n', '\r']);
+    if !needs_quotes { return out.write_str(field) }
+
+    out.write_char('"')?;
+    out.write_str(&field.replace('"', "\"\""))?;
+    out.write_char('"')
+}
+
+/// Reads a map back from a JSON table.
+pub fn parse_json(src: &str) -> Result<MapFile, ParseError> {
+    let root = json::parse(src).map_err(|e| ParseError { line: e.line, kind: ParseErrorKind::Syntax(e.msg) })?;
+
+    // the JSON parser doesn't keep line numbers, so errors past it are reported on line 1
+    let err = |kind| ParseError { line: 1, kind };
+    let mut mapfile = MapFile::default();
+
+    let names = root.get("sections").and_then(Value::as_array).unwrap_or(&[]);
+    for name in names {
+        let name = name.as_str().ok_or(err(ParseErrorKind::MissingColumn("section")))?;
+        section_for(&mut mapfile, name);
+    }
+
+    let entries = root.get("entries").and_then(Value::as_array).ok_or(err(ParseErrorKind::MissingColumn("entries")))?;
+    for row in entries {
+        let field = |column| row.get(column).ok_or(err(ParseErrorKind::MissingColumn(column)));
+        let addr = |column| {
+            let value = field(column)?;
+            value.as_str().and_then(parse_number).or(value.as_u32()).ok_or(err(ParseErrorKind::InvalidHex(column)))
+        };
+        let number = |column| {
+            let value = field(column)?;
+            value.as_u32().or(value.as_str().and_then(parse_number)).ok_or(err(ParseErrorKind::MissingColumn(column)))
+        };
+
+        let section = field("section")?.as_str().ok_or(err(ParseErrorKind::MissingColumn("section")))?;
+        let entry = MapEntry {
+            start: addr("start")?,
+            size: number("size")?,
+            vaddr: addr("vaddr")?,
+            align: number("align").map_err(|_| err(ParseErrorKind::InvalidAlign))?,
+            name: parse_name(field("name")?.as_str().unwrap_or("")).map_err(err)?,
+        };
+        section_for(&mut mapfile, section).entries.push(entry);
+    }
+
+    Ok(mapfile)
+}
+
+/// Reads a map back from a CSV table.
+pub fn parse_csv(src: &str) -> Result<MapFile, ParseError> {
+    parse_separated(src, ',')
+}
+
+/// Reads a map back from a TSV table.
+pub fn parse_tsv(src: &str) -> Result<MapFile, ParseError> {
+    parse_separated(src, '\t')
+}
+
+fn parse_separated(src: &str, separator: char) -> Result<MapFile, ParseError> {
+    let mut rows = split_rows(src, separator).into_iter().filter(|(_, row)| !row.iter().all(|f| f.is_empty()));
+    let mut mapfile = MapFile::default();
+
+    let Some((_, header)) = rows.next() else { return Ok(mapfile) };
+    let column = |name| header.iter().position(|h| h.trim() == name);
+    let indices = ["section", "start", "size", "vaddr", "align", "name"].map(column);
+
+    for (line, row) in rows {
+        let err = |kind| ParseError { line, kind };
+        // short rows are padded with empty fields
+        let field = |i: usize, name| match indices[i] {
+            Some(c) => Ok(row.get(c).map_or("", |f| f.trim())),
+            None => Err(err(ParseErrorKind::MissingColumn(name))),
+        };
+
+        let section = field(0, "section")?;
+        if section.is_empty() { return Err(err(ParseErrorKind::MissingColumn("section"))) }
+        let section = section_for(&mut mapfile, section);
+
+        // a row with only the section is an empty section
+        if field(1, "start")?.is_empty() && field(5, "name")?.is_empty() { continue }
+
+        let start = field(1, "start")?;
+        let size = field(2, "size")?;
+        let vaddr = field(3, "vaddr")?;
+        let align = field(4, "align")?;
+        section.entries.push(MapEntry {
+            start: parse_number(start).ok_or(err(ParseErrorKind::InvalidHex("start")))?,
+            size: parse_number(size).ok_or(err(ParseErrorKind::MissingColumn("size")))?,
+            vaddr: parse_number(vaddr).ok_or(err(ParseErrorKind::InvalidHex("vaddr")))?,
+            align: parse_number(align).ok_or(err(ParseErrorKind::InvalidAlign))?,
+            name: parse_name(field(5, "name")?).map_err(err)?,
+        });
+    }
+
+    Ok(mapfile)
+}
+
+/// Splits CSV or TSV into rows of fields, with the line number each row starts on.
+/// Only CSV fields may be quoted.
+fn split_rows(src: &str, separator: char) -> Vec<(usize, Vec<String>)> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut quoted = false;
+
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\n' { line += 1; }
+
+        match c {
+            '"' if separator == ',' && quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if separator == ',' && field.is_empty() => quoted = true,
+            _ if quoted => field.push(c),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push((row_line, std::mem::take(&mut row)));
+                row_line = line;
+            }
+            c if c == separator => row.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push((row_line, row));
+    }
+
+    rows
+}
+
+/// Refuses names the map can't hold, a blank name would leave an entry line without its name column.
+fn parse_name(name: &str) -> Result<String, ParseErrorKind> {
+    if name.is_empty() { return Err(ParseErrorKind::MissingColumn("name")) }
+    if !map::is_identifier(name) { return Err(ParseErrorKind::Syntax("name is not a valid identifier")) }
+    Ok(name.to_string())
+}
+
+/// Parses "0x" prefixed hex or decimal.
+fn parse_number(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Returns the section named `name`, adding it if there is none.
+fn section_for<'a>(mapfile: &'a mut MapFile, name: &str) -> &'a mut Section {
+    if mapfile.section(name).is_none() {
+        mapfile.sections.push(Section { name: name.to_string(), entries: Vec::new() });
+    }
+    mapfile.section_mut(name).unwrap()
+}
+
+/// A reason not to replace a map with an imported table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportProblem {
+    /// A check error the map didn't already have. Duplicate names and unsorted entries are
+    /// always refused.
+    Check(Problem),
+
+    /// A real name would be replaced by a placeholder. Only allowed with force.
+    PlaceholderName {
+        /// Start address of the entry.
+        addr: u32,
+        /// The entry's name in the map.
+        current: String,
+        /// The placeholder in the table.
+        name: String,
+    },
+
+    /// The table would not read back the same once written as a map, e.g. a section name
+    /// with a line break.
+    RoundTrip,
+}
+
+impl fmt::Display for ImportProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportProblem::Check(problem) => write!(f, "{}", problem),
+            ImportProblem::PlaceholderName { addr, current, name } => {
+                write!(f, "{:08x} {} -> {}: new name is a placeholder", addr, current, name)
+            }
+            ImportProblem::RoundTrip => write!(f, "the table cannot be written as a mapfile"),
+        }
+    }
+}
+
+/// Checks an imported table before it replaces `original`, returning no problems if it may.
+/// Placeholders may replace real names if `force` is set.
+pub fn validate(original: &MapFile, imported: &MapFile, force: bool) -> Vec<ImportProblem> {
+    let mut problems = Vec::new();
+
+    if MapFile::parse(&imported.to_string()).ok().as_ref() != Some(imported) {
+        problems.push(ImportProblem::RoundTrip);
+    }
+
+    // errors the map already had, such as overlaps Dolphin wrote, don't block the import
+    let existing = check::check(original);
+    for problem in check::check(imported) {
+        let always = matches!(problem.kind, ProblemKind::DuplicateName { .. } | ProblemKind::OutOfOrder { .. });
+        if problem.kind.is_error() && (always || !existing.contains(&problem)) {
+            problems.push(ImportProblem::Check(problem));
+        }
+    }
+
+    if !force {
+        for entry in imported.entries().filter(|e| map::is_placeholder(&e.name)) {
+            let Some((_, current)) = original.entry_at(entry.start) else { continue };
+            if !map::is_placeholder(&current.name) {
+                problems.push(ImportProblem::PlaceholderName {
+                    addr: entry.start,
+                    current: current.name.clone(),
+                    name: entry.name.clone(),
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80003130 0000c4 80003130 4 zz_80003130_
+
+.data section layout
+
+.sdata section layout
+804d36a0 000004 804d36a0 0 some_global
+";
+
+    const CSV: &str = "\
+section,start,size,vaddr,align,name,placeholder,object
+.text,0x80003100,48,0x80003100,0,memset,false,os.o
+.text,0x80003130,196,0x80003130,4,zz_80003130_,true,os.o
+.data
+.sdata,0x804d36a0,4,0x804d36a0,0,some_global,false,
+";
+
+    fn objects() -> Vec<Split> {
+        crate::dtk::parse_splits("os.o:\n\t.text start:0x80003100 end:0x80003200\n").unwrap()
+    }
+
+    fn write(format: fn(&MapFile, &[Split], &mut String) -> fmt::Result) -> String {
+        let mut out = String::new();
+        format(&MapFile::parse(MAP).unwrap(), &objects(), &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let csv = write(|m, o, out| write_separated(m, o, ',', out));
+        assert_eq!(csv, CSV);
+        assert_eq!(parse_csv(&csv).unwrap().to_string(), MAP);
+    }
+
+    #[test]
+    fn tsv_round_trip() {
+        let tsv = write(|m, o, out| write_separated(m, o, '\t', out));
+        assert_eq!(tsv, CSV.replace(',', "\t"));
+        assert_eq!(parse_tsv(&tsv).unwrap().to_string(), MAP);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let json = write(write_json);
+        assert!(json.starts_with("{\n    \"schema\": 1,\n    \"sections\": [\".text\", \".data\", \".sdata\"],\n"));
+        assert!(json.contains("\n        {\"section\": \".text\", \"start\": \"0x80003100\", \"size\": 48, \"vaddr\": \"0x80003100\", \"align\": 0, \"name\": \"memset\", \"placeholder\": false, \"object\": \"os.o\"},\n"));
+        assert!(json.contains("\"name\": \"some_global\", \"placeholder\": false, \"object\": null}\n    ]\n}\n"));
+        assert_eq!(parse_json(&json).unwrap().to_string(), MAP);
+    }
+
+    #[test]
+    fn empty_sections() {
+        let empty = MapFile::parse(".text section layout\n\n.data section layout\n").unwrap();
+
+        let mut csv = String::new();
+        write_separated(&empty, &[], ',', &mut csv).unwrap();
+        assert_eq!(csv, format!("{}\n.text\n.data\n", COLUMNS.join(",")));
+        assert_eq!(parse_csv(&csv).unwrap(), empty);
+
+        let mut json = String::new();
+        write_json(&empty, &[], &mut json).unwrap();
+        assert_eq!(parse_json(&json).unwrap(), empty);
+    }
+
+    #[test]
+    fn columns_in_any_order() {
+        let csv = "name,align,vaddr,size,start,section\nmemset,0,0x80003100,0x30,0x80003100,.text\n";
+        assert_eq!(parse_csv(csv).unwrap().to_string(), ".text section layout\n80003100 000030 80003100 0 memset\n");
+    }
+
+    #[test]
+    fn split_quoted_fields() {
+        let rows = split_rows("a,\"b,c\",\"say \"\"hi\"\"\"\r\n\"two\nlines\",,\"\"\nlast", ',');
+        assert_eq!(rows, [
+            (1, vec!["a".to_string(), "b,c".into(), "say \"hi\"".into()]),
+            (2, vec!["two\nlines".to_string(), "".into(), "".into()]),
+            (4, vec!["last".to_string()]),
+        ]);
+
+        // only CSV is quoted
+        let rows = split_rows("\"a\"\tb,c\n", '\t');
+        assert_eq!(rows, [(1, vec!["\"a\"".to_string(), "b,c".into()])]);
+    }
+
+    #[test]
+    fn write_quoted_fields() {
+        let mut out = String::new();
+        for field in ["plain", "a,b", "say \"hi\"", "two\nlines"] {
+            write_field(&mut out, field, ',').unwrap();
+            out.push(',');
+        }
+        assert_eq!(out, "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",");
+        assert_eq!(split_rows(&out, ',')[0].1, ["plain", "a,b", "say \"hi\"", "two\nlines", ""]);
+    }
+
+    #[test]
+    fn invalid_names() {
+        // a blank name would write an entry line that no longer parses
+        let blank = CSV.replace(",memset,", ",,");
+        assert_eq!(parse_csv(&blank), Err(ParseError { line: 2, kind: ParseErrorKind::MissingColumn("name") }));
+
+        let spaced = CSV.replace(",memset,", ",mem set,");
+        assert_eq!(parse_csv(&spaced), Err(ParseError { line: 2, kind: ParseErrorKind::Syntax("name is not a valid identifier") }));
+
+        let json = "{\"entries\": [{\"section\": \".text\", \"start\": \"0x80003100\", \"size\": 48, \"vaddr\": \"0x80003100\", \"align\": 0, \"name\": \"\"}]}";
+        assert_eq!(parse_json(json), Err(ParseError { line: 1, kind: ParseErrorKind::MissingColumn("name") }));
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        let csv = CSV.replace("0x80003100,48", "80003100x,48");
+        assert_eq!(parse_csv(&csv), Err(ParseError { line: 2, kind: ParseErrorKind::InvalidHex("start") }));
+        let csv = CSV.replace(",4,zz", ",four,zz");
+        assert_eq!(parse_csv(&csv), Err(ParseError { line: 3, kind: ParseErrorKind::InvalidAlign }));
+        let csv = CSV.replace("section,", "");
+        assert_eq!(parse_csv(&csv), Err(ParseError { line: 2, kind: ParseErrorKind::MissingColumn("section") }));
+    }
+
+    #[test]
+    fn validate_accepts_unchanged() {
+        let original = MapFile::parse(MAP).unwrap();
+        assert_eq!(validate(&original, &original, false), []);
+
+        let mut renamed = original.clone();
+        renamed.sections[0].entries[1].name = "memset_internal".into();
+        assert_eq!(validate(&original, &renamed, false), []);
+    }
+
+    #[test]
+    fn validate_keeps_existing_errors() {
+        // overlaps already in the map don't block the import, new ones do
+        let original = MapFile::parse(&MAP.replace("80003100 000030", "80003100 000040")).unwrap();
+        assert_eq!(validate(&original, &original, false), []);
+
+        let mut imported = original.clone();
+        imported.sections[0].entries[1].size = 0x200;
+        imported.sections[0].entries.push(MapEntry { start: 0x80003200, size: 4, vaddr: 0x80003200, align: 0, name: "next".into() });
+        let problems = validate(&original, &imported, false);
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(&problems[0], ImportProblem::Check(p) if p.name == "zz_80003130_" && matches!(p.kind, ProblemKind::Overlap { .. })));
+    }
+
+    #[test]
+    fn validate_duplicates_and_order() {
+        let original = MapFile::parse(MAP).unwrap();
+
+        let mut imported = original.clone();
+        imported.sections[0].entries[1].name = "some_global".into();
+        assert_eq!(validate(&original, &imported, true), [ImportProblem::Check(Problem {
+            section: ".sdata".into(),
+            addr: 0x804d36a0,
+            name: "some_global".into(),
+            kind: ProblemKind::DuplicateName { other_addr: 0x80003130 },
+        })]);
+
+        let mut imported = original.clone();
+        imported.sections[0].entries.swap(0, 1);
+        assert!(validate(&original, &imported, true).iter().any(|p| matches!(p, ImportProblem::Check(Problem { kind: ProblemKind::OutOfOrder { .. }, .. }))));
+    }
+
+    #[test]
+    fn validate_placeholder_names() {
+        let original = MapFile::parse(MAP).unwrap();
+        let mut imported = original.clone();
+        imported.sections[0].entries[0].name = "zz_80003100_".into();
+
+        assert_eq!(validate(&original, &imported, false), [ImportProblem::PlaceholderName {
+            addr: 0x80003100,
+            current: "memset".into(),
+            name: "zz_80003100_".into(),
+        }]);
+        assert_eq!(validate(&original, &imported, true), []);
+    }
+
+    #[test]
+    fn validate_round_trip() {
+        let original = MapFile::parse(MAP).unwrap();
+        let mut imported = original.clone();
+        imported.sections[1].name = ".data\n.bss".into();
+        assert_eq!(validate(&original, &imported, false), [ImportProblem::RoundTrip]);
+    }
+}
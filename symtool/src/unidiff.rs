@@ -0,0 +1,270 @@
+//! Unified diffs for previewing edits.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Unified diffs for previewing map edits.
+//
+// Lines are matched with patience diff: lines that occur exactly once on both sides
+// anchor the diff, and the regions between anchors are diffed recursively. Nearly every
+// map line is unique thanks to its address, so this is fast and gives readable hunks.
+// Unmatched regions are written as alternating removed and added lines, so a rename
+// shows up as a '-' line directly followed by its '+' line.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Returns a unified diff from `old` to `new` with `context` lines around each change,
+/// or an empty string if they are equal.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str, context: usize) -> String {
+    let a = old.lines().collect::<Vec<_>>();
+    let b = new.lines().collect::<Vec<_>>();
+
+    let mut ops = Vec::new();
+    diff_range(&a, &b, 0..a.len(), 0..b.len(), &mut ops);
+
+    let mut out = String::new();
+    if ops.iter().all(|op| matches!(op, Op::Equal(..))) { return out }
+
+    writeln!(out, "--- {}", old_name).unwrap();
+    writeln!(out, "+++ {}", new_name).unwrap();
+
+    // split into hunks at runs of more than 2*context equal lines
+    let mut i = 0;
+    let mut old_pos = 0;
+    let mut new_pos = 0;
+    while i < ops.len() {
+        let Some(first_change) = ops[i..].iter().position(|op| !matches!(op, Op::Equal(..))) else { break };
+        let hunk_start = (i + first_change).saturating_sub(context).max(i);
+
+        let mut hunk_end = i + first_change;
+        let mut equal_run = 0;
+        for (j, op) in ops.iter().enumerate().skip(hunk_end) {
+            if matches!(op, Op::Equal(..)) {
+                equal_run += 1;
+                if equal_run > 2 * context { break }
+            } else {
+                equal_run = 0;
+                hunk_end = j + 1;
+            }
+        }
+        let hunk_end = (hunk_end + context).min(ops.len());
+
+        for op in ops[i..hunk_start].iter() {
+            advance(op, &mut old_pos, &mut new_pos);
+        }
+        write_hunk(&mut out, &a, &b, &ops[hunk_start..hunk_end], old_pos, new_pos);
+        for op in ops[hunk_start..hunk_end].iter() {
+            advance(op, &mut old_pos, &mut new_pos);
+        }
+        i = hunk_end;
+    }
+
+    out
+}
+
+fn advance(op: &Op, old_pos: &mut usize, new_pos: &mut usize) {
+    match op {
+        Op::Equal(..) => { *old_pos += 1; *new_pos += 1; }
+        Op::Delete(_) => *old_pos += 1,
+        Op::Insert(_) => *new_pos += 1,
+    }
+}
+
+/// `old_pos` and `new_pos` are the number of lines before the hunk.
+fn write_hunk(out: &mut String, a: &[&str], b: &[&str], ops: &[Op], old_pos: usize, new_pos: usize) {
+    let old_len = ops.iter().filter(|op| !matches!(op, Op::Insert(_))).count();
+    let new_len = ops.iter().filter(|op| !matches!(op, Op::Delete(_))).count();
+
+    // empty ranges are numbered by the line before them
+    let range_start = |pos, len| if len == 0 { pos } else { pos + 1 };
+    writeln!(
+        out, "@@ -{},{} +{},{} @@",
+        range_start(old_pos, old_len), old_len, range_start(new_pos, new_len), new_len,
+    ).unwrap();
+
+    for op in ops {
+        match *op {
+            Op::Equal(i, _) => writeln!(out, " {}", a[i]).unwrap(),
+            Op::Delete(i) => writeln!(out, "-{}", a[i]).unwrap(),
+            Op::Insert(j) => writeln!(out, "+{}", b[j]).unwrap(),
+        }
+    }
+}
+
+fn diff_range(
+    a: &[&str],
+    b: &[&str],
+    mut ar: std::ops::Range<usize>,
+    mut br: std::ops::Range<usize>,
+    ops: &mut Vec<Op>,
+) {
+    // common prefix
+    while !ar.is_empty() && !br.is_empty() && a[ar.start] == b[br.start] {
+        ops.push(Op::Equal(ar.start, br.start));
+        ar.start += 1;
+        br.start += 1;
+    }
+
+    // common suffix, pushed after the middle
+    let mut suffix = 0;
+    while ar.len() > suffix && br.len() > suffix && a[ar.end-1-suffix] == b[br.end-1-suffix] {
+        suffix += 1;
+    }
+    ar.end -= suffix;
+    br.end -= suffix;
+
+    let anchors = unique_anchors(a, b, ar.clone(), br.clone());
+    if anchors.is_empty() {
+        // no common lines, interleave removed and added lines
+        for k in 0..ar.len().max(br.len()) {
+            if k < ar.len() { ops.push(Op::Delete(ar.start + k)); }
+            if k < br.len() { ops.push(Op::Insert(br.start + k)); }
+        }
+    } else {
+        let (mut ai, mut bi) = (ar.start, br.start);
+        for (i, j) in anchors {
+            diff_range(a, b, ai..i, bi..j, ops);
+            ops.push(Op::Equal(i, j));
+            ai = i + 1;
+            bi = j + 1;
+        }
+        diff_range(a, b, ai..ar.end, bi..br.end, ops);
+    }
+
+    for k in 0..suffix {
+        ops.push(Op::Equal(ar.end + k, br.end + k));
+    }
+}
+
+/// Lines occurring exactly once in both ranges, as the longest sequence
+/// of (a index, b index) pairs that is increasing on both sides.
+fn unique_anchors(
+    a: &[&str],
+    b: &[&str],
+    ar: std::ops::Range<usize>,
+    br: std::ops::Range<usize>,
+) -> Vec<(usize, usize)> {
+    // line -> (count in a, index in a, count in b, index in b)
+    let mut counts = HashMap::<&str, (u32, usize, u32, usize)>::new();
+    for i in ar.clone() {
+        let c = counts.entry(a[i]).or_default();
+        c.0 += 1;
+        c.1 = i;
+    }
+    for j in br.clone() {
+        if let Some(c) = counts.get_mut(b[j]) {
+            c.2 += 1;
+            c.3 = j;
+        }
+    }
+
+    let mut pairs = ar
+        .filter_map(|i| match counts[a[i]] {
+            (1, _, 1, j) => Some((i, j)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    pairs.sort_unstable();
+
+    longest_increasing(&pairs)
+}
+
+/// Longest subsequence of `pairs` (sorted by .0) that is also increasing in .1.
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // patience sorting: tops[k] is the index of the smallest tail of an increasing run of length k+1
+    let mut tops = Vec::<usize>::new();
+    let mut prev = vec![usize::MAX; pairs.len()];
+
+    for (n, &(_, j)) in pairs.iter().enumerate() {
+        let k = tops.partition_point(|&t| pairs[t].1 < j);
+        if k > 0 { prev[n] = tops[k-1]; }
+        if k == tops.len() { tops.push(n) } else { tops[k] = n }
+    }
+
+    let mut seq = Vec::with_capacity(tops.len());
+    let mut n = tops.last().copied().unwrap_or(usize::MAX);
+    while n != usize::MAX {
+        seq.push(pairs[n]);
+        n = prev[n];
+    }
+    seq.reverse();
+    seq
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies a unified diff, checking that its context and removed lines match `old`.
+    fn patch(old: &str, diff: &str) -> String {
+        let old = old.lines().collect::<Vec<_>>();
+        let mut out = Vec::new();
+        let mut pos = 0;
+
+        for line in diff.lines().skip(2) {
+            if let Some(header) = line.strip_prefix("@@ -") {
+                let start = header.split([',', ' ']).next().unwrap().parse::<usize>().unwrap();
+                // a hunk of an empty range starts after the given line
+                let start = if header.split(' ').next().unwrap().ends_with(",0") { start } else { start - 1 };
+                out.extend_from_slice(&old[pos..start]);
+                pos = start;
+            } else if let Some(line) = line.strip_prefix('+') {
+                out.push(line);
+            } else {
+                assert_eq!(old[pos], &line[1..]);
+                if line.starts_with(' ') { out.push(old[pos]); }
+                pos += 1;
+            }
+        }
+        out.extend_from_slice(&old[pos..]);
+        out.iter().map(|l| format!("{}\n", l)).collect()
+    }
+
+    fn lines(n: usize) -> String {
+        (0..n).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    #[test]
+    fn equal() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "a/x", "b/x", 3), "");
+    }
+
+    #[test]
+    fn rename_hunk() {
+        let old = lines(10);
+        let new = old.replace("line 5\n", "line five\n");
+        let diff = unified_diff(&old, &new, "a/x", "b/x", 2);
+        assert_eq!(diff, "--- a/x\n+++ b/x\n@@ -4,5 +4,5 @@\n line 3\n line 4\n-line 5\n+line five\n line 6\n line 7\n");
+    }
+
+    #[test]
+    fn interleaves_replaced_lines() {
+        let old = "a\nb\nc\nd\n";
+        let new = "a\nB\nC\nd\n";
+        let diff = unified_diff(old, new, "a/x", "b/x", 1);
+        assert!(diff.ends_with(" a\n-b\n+B\n-c\n+C\n d\n"), "{}", diff);
+    }
+
+    #[test]
+    fn separate_hunks_apply() {
+        let old = lines(40);
+        let new = old
+            .replace("line 2\n", "")
+            .replace("line 20\n", "line 20\ninserted\n")
+            .replace("line 39\n", "last\n");
+        let diff = unified_diff(&old, &new, "a/x", "b/x", 3);
+        assert_eq!(diff.matches("\n@@ ").count(), 3);
+        assert_eq!(patch(&old, &diff), new);
+    }
+
+    #[test]
+    fn insert_into_empty() {
+        let diff = unified_diff("", "a\n", "a/x", "b/x", 3);
+        assert_eq!(diff, "--- a/x\n+++ b/x\n@@ -0,0 +1,1 @@\n+a\n");
+    }
+}
@@ -0,0 +1,852 @@
+//! Renaming map entries from free-form symbol and address lists.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::diff::{DiffEntry, Resized};
+use crate::lookup::{AddrIndex, Location};
+use crate::map::{self, MapEntry, MapFile, Section};
+use crate::symaddr::line_symaddr;
+
+/// A requested name for the map entry at some address, read from one line of input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpdateRecord {
+    /// Start address of the entry to name.
+    pub addr: u32,
+    /// The requested name.
+    pub name: String,
+    /// Section named on the input line, if any. The entry must be in this section.
+    pub section: Option<String>,
+    /// Size given on the input line, if any. Used when inserting new entries,
+    /// and with [`UpdateOptions::layout`].
+    pub size: Option<u32>,
+    /// Virtual address given on the input line, if any. Only Dolphin map entries carry one.
+    pub vaddr: Option<u32>,
+    /// Alignment given on the input line, if any.
+    pub align: Option<u32>,
+}
+
+/// A name change applied to the map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rename {
+    /// Section of the entry.
+    pub section: String,
+    /// Start address of the entry.
+    pub addr: u32,
+    /// Name before the change.
+    pub old: String,
+    /// Name after the change.
+    pub new: String,
+}
+
+/// A change to the vaddr or align column of an entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Realigned {
+    /// Section of the entry.
+    pub section: String,
+    /// Start address of the entry.
+    pub addr: u32,
+    /// Name of the entry.
+    pub name: String,
+    /// Virtual address before the change.
+    pub old_vaddr: u32,
+    /// Virtual address after the change.
+    pub new_vaddr: u32,
+    /// Alignment before the change.
+    pub old_align: u32,
+    /// Alignment after the change.
+    pub new_align: u32,
+}
+
+/// An update record that was not applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Skipped {
+    /// The record as given.
+    pub record: UpdateRecord,
+    /// Why it was not applied.
+    pub reason: SkipReason,
+}
+
+/// Why an update record was not applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The entry at this address is in a different section than the record asked for.
+    SectionMismatch {
+        /// Section the entry is in.
+        actual: String,
+    },
+
+    /// The entry already has a real name. Only replaced with [`UpdateOptions::force`].
+    RealName {
+        /// The entry's name.
+        current: String,
+    },
+
+    /// The record's name is a placeholder, which would lose information.
+    /// Only applied with [`UpdateOptions::force`].
+    PlaceholderName {
+        /// The entry's name.
+        current: String,
+    },
+
+    /// The name is already used by the entry at `other_addr`.
+    /// Only applied with [`UpdateOptions::move_duplicates`].
+    DuplicateName {
+        /// Start address of the entry with the name.
+        other_addr: u32,
+    },
+
+    /// Another record in the same input gives this name to `other_addr`.
+    DuplicateInInput {
+        /// Address of the other record.
+        other_addr: u32,
+    },
+
+    /// The record's name is not a valid C identifier.
+    InvalidName,
+
+    /// A new entry could not be inserted because the address is not within any section,
+    /// and the record names no section that could be added.
+    NoSection,
+
+    /// A new entry could not be inserted because its size is unknown, and there is no following entry.
+    UnknownSize,
+
+    /// The entry could not be inserted or resized because it would overlap the entry at `next_addr`.
+    Overlap {
+        /// Name of the next entry.
+        next: String,
+        /// Start address of the next entry.
+        next_addr: u32,
+    },
+}
+
+/// Options of [`apply`]. The default only renames placeholders.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateOptions {
+    /// Overwrite real names, and allow renaming entries to placeholders.
+    pub force: bool,
+
+    /// If a name is already used at another address, move it to the new address
+    /// and give the old entry a placeholder name.
+    pub move_duplicates: bool,
+
+    /// Insert new entries for addresses without one.
+    pub insert: bool,
+
+    /// Also update the size, vaddr and align columns from the records.
+    pub layout: bool,
+}
+
+/// What [`apply`] changed, and which records it skipped.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateReport {
+    /// Applied renames, in map order.
+    pub renamed: Vec<Rename>,
+    /// Inserted entries, with the names they were inserted with. They are then renamed like existing entries.
+    pub inserted: Vec<DiffEntry>,
+    /// Entries that were shrunk to make room for inserted entries, or resized by records
+    /// with [`UpdateOptions::layout`].
+    pub resized: Vec<Resized>,
+    /// Entries given a new vaddr or align by records with [`UpdateOptions::layout`].
+    pub realigned: Vec<Realigned>,
+    /// Records that were not applied.
+    pub skipped: Vec<Skipped>,
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let record = &self.record;
+        match &self.reason {
+            SkipReason::SectionMismatch { actual } => write!(
+                f, "{:08x} {}: expected section {}, but the entry is in {}",
+                record.addr, record.name, record.section.as_deref().unwrap_or(""), actual,
+            ),
+            SkipReason::RealName { current } => write!(
+                f, "{:08x} {} -> {}: not overwriting a real name",
+                record.addr, current, record.name,
+            ),
+            SkipReason::PlaceholderName { current } => write!(
+                f, "{:08x} {} -> {}: new name is a placeholder",
+                record.addr, current, record.name,
+            ),
+            SkipReason::DuplicateName { other_addr } => write!(
+                f, "{:08x} {}: name is already used at {:08x}",
+                record.addr, record.name, other_addr,
+            ),
+            SkipReason::DuplicateInInput { other_addr } => write!(
+                f, "{:08x} {}: name is also given to {:08x} in the same input",
+                record.addr, record.name, other_addr,
+            ),
+            SkipReason::InvalidName => write!(
+                f, "{:08x} {}: name is not a valid identifier",
+                record.addr, record.name,
+            ),
+            SkipReason::NoSection => write!(
+                f, "{:08x} {}: address is not within any section, and no known section was given",
+                record.addr, record.name,
+            ),
+            SkipReason::UnknownSize => write!(
+                f, "{:08x} {}: no size given and no following entry",
+                record.addr, record.name,
+            ),
+            SkipReason::Overlap { next, next_addr } => write!(
+                f, "{:08x} {}: would overlap {} at {:08x}",
+                record.addr, record.name, next, next_addr,
+            ),
+        }
+    }
+}
+
+impl UpdateRecord {
+    /// Finds a symbol and address on a line of free-form text, see [`line_symaddr`].
+    ///
+    /// A section name such as `.data` anywhere on the line restricts the update to that section.
+    /// `size:0x30` and `align:4` tokens (or `size=48`) give the entry's layout. A whole Dolphin
+    /// map entry line gives its size, vaddr and align.
+    pub fn parse_line(line: &str) -> Option<UpdateRecord> {
+        // Dolphin map entries carry their whole layout
+        if let Ok(entry) = MapEntry::parse(line.trim()) && (0x80000000..0x81800000).contains(&entry.start) {
+            return Some(UpdateRecord {
+                addr: entry.start,
+                name: entry.name,
+                section: None,
+                size: Some(entry.size),
+                vaddr: Some(entry.vaddr),
+                align: Some(entry.align),
+            });
+        }
+
+        let mut line = line.to_string();
+        let mut section = None;
+
+        if let Some(range) = find_section_name(&line) {
+            section = Some(line[range.clone()].to_string());
+
+            // don't let line_symaddr pick up the section as the symbol
+            line.replace_range(range.clone(), &" ".repeat(range.len()));
+        }
+
+        let mut take_field = |key| {
+            let (value, range) = find_field(&line, key)?;
+            line.replace_range(range.clone(), &" ".repeat(range.len()));
+            Some(value)
+        };
+        let size = take_field("size");
+        let align = take_field("align");
+
+        let info = line_symaddr(&line)?;
+        Some(UpdateRecord { addr: info.addr, name: info.symbol.to_string(), section, size, vaddr: None, align })
+    }
+}
+
+/// Renames map entries by start address.
+///
+/// If several records share an address, the last one wins. Records whose address
+/// has no entry in the map are ignored. Unless forced, only placeholder names are
+/// replaced, and never with another placeholder.
+///
+/// A name is never given to two entries. Records whose name is already used at another
+/// address are skipped, unless [`UpdateOptions::move_duplicates`] is set. Records that
+/// give the same name to several addresses are always skipped.
+///
+/// With [`UpdateOptions::insert`], records for addresses without an entry first insert
+/// a placeholder entry, see [`insert_entry`]. If the record's name is then refused as
+/// invalid or already used, nothing is inserted for it.
+///
+/// With [`UpdateOptions::layout`], the size, vaddr and align given by a record are written
+/// to its entry, independently of the name. Sizes that would overlap the next entry are skipped.
+pub fn apply(mapfile: &mut MapFile, records: &[UpdateRecord], options: UpdateOptions) -> UpdateReport {
+    if !options.insert { return apply_inserting(mapfile, records, options, &[]) }
+
+    // Inserts go first so the inserted entries can be renamed like any other, but an entry
+    // whose name is refused would be left behind as a bare placeholder. Start over without
+    // inserting those until no inserted entry is refused.
+    let original = mapfile.clone();
+    let mut refused = Vec::<Skipped>::new();
+    loop {
+        let mut report = apply_inserting(mapfile, records, options, &refused);
+
+        let inserted = report.inserted.iter().map(|e| e.entry.start).collect::<HashSet<u32>>();
+        let newly_refused = report.skipped.iter()
+            .filter(|s| inserted.contains(&s.record.addr))
+            .filter(|s| matches!(
+                s.reason,
+                SkipReason::InvalidName | SkipReason::DuplicateInInput { .. } | SkipReason::DuplicateName { .. },
+            ))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if newly_refused.is_empty() {
+            report.skipped.splice(0..0, refused);
+            return report;
+        }
+
+        *mapfile = original.clone();
+        refused.extend(newly_refused);
+    }
+}
+
+/// [`apply`], without inserting entries for the records in `refused`.
+fn apply_inserting(mapfile: &mut MapFile, records: &[UpdateRecord], options: UpdateOptions, refused: &[Skipped]) -> UpdateReport {
+    let mut updates = HashMap::<u32, &UpdateRecord>::new();
+    for record in records {
+        updates.insert(record.addr, record);
+    }
+    
+    let mut report = UpdateReport::default();
+
+    // without insert, records for unknown addresses are ignored
+    let existing = mapfile.entries().map(|e| e.start).collect::<HashSet<u32>>();
+    let mut missing = updates.values()
+        .filter(|r| options.insert && !existing.contains(&r.addr))
+        .filter(|r| !refused.iter().any(|s| s.record.addr == r.addr))
+        .copied()
+        .collect::<Vec<_>>();
+    missing.sort_by_key(|r| r.addr);
+
+    for record in missing {
+        match insert_entry(mapfile, record) {
+            Ok((inserted, resized)) => {
+                report.inserted.push(inserted);
+                report.resized.extend(resized);
+            }
+            Err(reason) => {
+                report.skipped.push(Skipped { record: record.clone(), reason });
+                updates.remove(&record.addr);
+            }
+        }
+    }
+
+    // addresses each name is requested for
+    let mut requested = HashMap::<&str, Vec<u32>>::new();
+    for record in updates.values() {
+        requested.entry(&record.name).or_default().push(record.addr);
+    }
+
+    // (section index, entry index) -> record
+    let mut planned = Vec::<((usize, usize), &UpdateRecord)>::new();
+    let mut planned_layout = Vec::<(usize, usize)>::new();
+
+    for (si, section) in mapfile.sections.iter().enumerate() {
+        for (ei, entry) in section.entries.iter().enumerate() {
+            let Some(&record) = updates.get(&entry.start) else { continue };
+
+            // never move a symbol between sections
+            if let Some(expected) = &record.section && *expected != section.name {
+                if entry.name != record.name || options.layout {
+                    let reason = SkipReason::SectionMismatch { actual: section.name.clone() };
+                    report.skipped.push(Skipped { record: record.clone(), reason });
+                }
+                continue;
+            }
+
+            if options.layout {
+                // only check new sizes, the map may already have overlaps
+                let size = record.size.filter(|&size| size != entry.size);
+                let next = section.entries.get(ei+1).filter(|next| next.start >= entry.start);
+                if let Some(size) = size && let Some(next) = next && entry.start as u64 + size as u64 > next.start as u64 {
+                    let reason = SkipReason::Overlap { next: next.name.clone(), next_addr: next.start };
+                    report.skipped.push(Skipped { record: record.clone(), reason });
+                } else {
+                    planned_layout.push((si, ei));
+                }
+            }
+
+            // nothing to do
+            if entry.name == record.name { continue }
+
+            let reason = if !map::is_identifier(&record.name) {
+                Some(SkipReason::InvalidName)
+            } else if let Some(&other_addr) = requested[record.name.as_str()].iter().find(|&&a| a != record.addr) {
+                Some(SkipReason::DuplicateInInput { other_addr })
+            } else if options.force {
+                None
+            } else if !map::is_placeholder(&entry.name) {
+                Some(SkipReason::RealName { current: entry.name.clone() })
+            } else if map::is_placeholder(&record.name) {
+                Some(SkipReason::PlaceholderName { current: entry.name.clone() })
+            } else {
+                None
+            };
+
+            match reason {
+                Some(reason) => report.skipped.push(Skipped { record: record.clone(), reason }),
+                None => planned.push(((si, ei), record)),
+            }
+        }
+    }
+
+    // current holder of each name
+    let mut holders = HashMap::<&str, (usize, usize)>::new();
+    for (si, section) in mapfile.sections.iter().enumerate() {
+        for (ei, entry) in section.entries.iter().enumerate() {
+            holders.entry(&entry.name).or_insert((si, ei));
+        }
+    }
+
+    // Resolve names that are already in use. A name is free if its holder is renamed in
+    // this batch, but skipping that rename takes the name again, so repeat until stable.
+    let mut new_names = HashMap::<(usize, usize), String>::new();
+    let mut duplicates = HashMap::<(usize, usize), u32>::new();
+    loop {
+        let mut changed = false;
+
+        for &(loc, record) in planned.iter() {
+            if duplicates.contains_key(&loc) { continue }
+
+            let Some(&holder) = holders.get(record.name.as_str()) else { continue };
+            if holder == loc { continue }
+
+            let holder_renamed = planned.iter().any(|&(l, _)| l == holder && !duplicates.contains_key(&l));
+            if holder_renamed { continue }
+
+            if options.move_duplicates {
+                let holder_entry = &mapfile.sections[holder.0].entries[holder.1];
+                new_names.insert(holder, map::placeholder_name(holder_entry.start));
+            } else {
+                duplicates.insert(loc, mapfile.sections[holder.0].entries[holder.1].start);
+                changed = true;
+            }
+        }
+
+        if !changed { break }
+    }
+
+    for &(loc, record) in planned.iter() {
+        match duplicates.get(&loc) {
+            Some(&other_addr) => report.skipped.push(Skipped {
+                record: record.clone(),
+                reason: SkipReason::DuplicateName { other_addr },
+            }),
+            None => { new_names.insert(loc, record.name.clone()); }
+        }
+    }
+
+    for &(si, ei) in planned_layout.iter() {
+        let section = &mut mapfile.sections[si];
+        let entry = &mut section.entries[ei];
+        let record = updates[&entry.start];
+
+        let size = record.size.unwrap_or(entry.size);
+        if size != entry.size {
+            report.resized.push(Resized {
+                section: section.name.clone(),
+                addr: entry.start,
+                name: entry.name.clone(),
+                old_size: entry.size,
+                new_size: size,
+            });
+            entry.size = size;
+        }
+
+        let vaddr = record.vaddr.unwrap_or(entry.vaddr);
+        let align = record.align.unwrap_or(entry.align);
+        if vaddr != entry.vaddr || align != entry.align {
+            report.realigned.push(Realigned {
+                section: section.name.clone(),
+                addr: entry.start,
+                name: entry.name.clone(),
+                old_vaddr: entry.vaddr,
+                new_vaddr: vaddr,
+                old_align: entry.align,
+                new_align: align,
+            });
+            entry.vaddr = vaddr;
+            entry.align = align;
+        }
+    }
+
+    for (si, section) in mapfile.sections.iter_mut().enumerate() {
+        for (ei, entry) in section.entries.iter_mut().enumerate() {
+            let Some(new) = new_names.remove(&(si, ei)) else { continue };
+
+            let old = std::mem::replace(&mut entry.name, new.clone());
+            report.renamed.push(Rename { section: section.name.clone(), addr: entry.start, old, new });
+        }
+    }
+
+    report
+}
+
+/// Inserts a placeholder entry at `record.addr`.
+///
+/// The entry goes into the record's section if given, otherwise the section whose entries
+/// span the address. A record's section is refused if the address lies within another
+/// section, and added to the map if it is missing, at its place in [`map::SECTION_NAMES`].
+///
+/// If the address is inside an existing entry, that entry is shrunk to end at the new one.
+/// The new entry takes the record's size if given, otherwise it extends to the end of the
+/// entry it was split from, or to the next entry.
+pub fn insert_entry(mapfile: &mut MapFile, record: &UpdateRecord) -> Result<(DiffEntry, Option<Resized>), SkipReason> {
+    let addr = record.addr;
+    let spans = |entries: &[MapEntry]| match (entries.first(), entries.iter().map(|e| e.start as u64 + e.size as u64).max()) {
+        (Some(first), Some(end)) => first.start <= addr && (addr as u64) < end,
+        _ => false,
+    };
+
+    // a section given by the record must not hide that the address belongs to another one
+    if let Some(name) = &record.section {
+        let actual = match AddrIndex::new(mapfile).locate(addr) {
+            Location::Symbol { section, .. } | Location::Gap { section, .. } => Some(section.name.clone()),
+            Location::Outside => None,
+        };
+        if let Some(actual) = actual && actual != *name {
+            return Err(SkipReason::SectionMismatch { actual });
+        }
+    }
+
+    let section = match &record.section {
+        Some(name) => {
+            if mapfile.section(name).is_none() {
+                // the new section's only entry has nothing to take its size from
+                if record.size.is_none() { return Err(SkipReason::UnknownSize) }
+                add_section(mapfile, name).ok_or(SkipReason::NoSection)?;
+            }
+            mapfile.section_mut(name).unwrap()
+        }
+        None => mapfile.sections.iter_mut().find(|s| spans(&s.entries)).ok_or(SkipReason::NoSection)?,
+    };
+
+    // entries are ordered by address, i is where the new entry goes
+    let i = section.entries.partition_point(|e| e.start <= addr);
+    let containing = i.checked_sub(1).filter(|&p| {
+        let prev = &section.entries[p];
+        (addr as u64) < prev.start as u64 + prev.size as u64
+    });
+    let next = section.entries.get(i);
+
+    let size = match (record.size, containing, next) {
+        (Some(size), _, _) => size,
+        (None, Some(p), _) => section.entries[p].end() - addr,
+        (None, None, Some(next)) => next.start - addr,
+        (None, None, None) => return Err(SkipReason::UnknownSize),
+    };
+
+    if let Some(next) = next && addr as u64 + size as u64 > next.start as u64 {
+        return Err(SkipReason::Overlap { next: next.name.clone(), next_addr: next.start });
+    }
+
+    let align = record.align.or(containing.or(i.checked_sub(1)).map(|p| section.entries[p].align)).unwrap_or(0);
+    let vaddr = record.vaddr.unwrap_or(addr);
+    let entry = MapEntry { start: addr, size, vaddr, align, name: map::placeholder_name(addr) };
+
+    let mut resized = None;
+    if let Some(p) = containing {
+        let prev = &mut section.entries[p];
+        let new_size = addr - prev.start;
+        resized = Some(Resized {
+            section: section.name.clone(),
+            addr: prev.start,
+            name: prev.name.clone(),
+            old_size: prev.size,
+            new_size,
+        });
+        prev.size = new_size;
+    }
+
+    section.entries.insert(i, entry.clone());
+    Ok((DiffEntry { section: section.name.clone(), entry }, resized))
+}
+
+/// Adds an empty section in the order of [`map::SECTION_NAMES`], e.g. `.sdata` after `.data`.
+/// Sections with other names are never added. Dolphin only writes the sections it found
+/// symbols in, so GALE01.map has no `.sdata` or `.bss` of its own.
+fn add_section<'a>(mapfile: &'a mut MapFile, name: &str) -> Option<&'a mut Section> {
+    let order = |name: &str| map::SECTION_NAMES.iter().position(|&n| n == name);
+    let new = order(name)?;
+
+    // after the last known section that comes before it, so unknown ones like .note stay last
+    let i = mapfile.sections.iter().rposition(|s| order(&s.name).is_some_and(|o| o < new)).map_or(0, |i| i + 1);
+    mapfile.sections.insert(i, Section { name: name.to_string(), entries: Vec::new() });
+    Some(&mut mapfile.sections[i])
+}
+
+/// Finds a `key:0x30` or `key=48` token, returning the value and the token's range.
+fn find_field(line: &str, key: &str) -> Option<(u32, std::ops::Range<usize>)> {
+    let bytes = line.as_bytes();
+
+    for (start, _) in line.match_indices(key) {
+        // must not be part of a larger token, e.g. 'fontsize'
+        if start > 0 && (bytes[start-1].is_ascii_alphanumeric() || bytes[start-1] == b'_') { continue }
+
+        let rest = &line[start+key.len()..];
+        let Some(value) = rest.strip_prefix([':', '=']) else { continue };
+        let value = value.trim_start();
+
+        let len = value.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(value.len());
+        let digits = &value[..len];
+        let parsed = match digits.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => digits.parse(),
+        };
+
+        if let Ok(parsed) = parsed {
+            let end = line.len() - value.len() + len;
+            return Some((parsed, start..end));
+        }
+    }
+
+    None
+}
+
+fn find_section_name(line: &str) -> Option<std::ops::Range<usize>> {
+    let bytes = line.as_bytes();
+
+    for (start, _) in line.match_indices('.') {
+        // must not be part of a larger token, e.g. 'file.data'
+        if start > 0 && (bytes[start-1].is_ascii_alphanumeric() || bytes[start-1] == b'_') { continue }
+
+        let len = line[start+1..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(line.len() - start - 1);
+        let range = start..start+1+len;
+
+        if map::SECTION_NAMES.contains(&&line[range.clone()]) { return Some(range) }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+.text section layout
+80003100 000030 80003100 0 memset
+80005340 00001c 80005340 0 zz_80005340_
+8000535c 0000c0 8000535c 0 zz_8000535c_
+
+.data section layout
+803b7240 000010 803b7240 0 zz_803b7240_
+";
+
+    fn record(addr: u32, name: &str) -> UpdateRecord {
+        UpdateRecord { addr, name: name.into(), section: None, size: None, vaddr: None, align: None }
+    }
+
+    fn apply_str(records: &[UpdateRecord], options: UpdateOptions) -> (MapFile, UpdateReport) {
+        let mut mapfile = MapFile::parse(MAP).unwrap();
+        let report = apply(&mut mapfile, records, options);
+        (mapfile, report)
+    }
+
+    fn reasons(report: &UpdateReport) -> Vec<SkipReason> {
+        report.skipped.iter().map(|s| s.reason.clone()).collect()
+    }
+
+    #[test]
+    fn renames_placeholders() {
+        let (mapfile, report) = apply_str(&[record(0x80005340, "Foo"), record(0x12345678, "Nowhere")], UpdateOptions::default());
+        assert_eq!(mapfile.entry_at(0x80005340).unwrap().1.name, "Foo");
+        assert_eq!(report.renamed, [Rename { section: ".text".into(), addr: 0x80005340, old: "zz_80005340_".into(), new: "Foo".into() }]);
+        assert_eq!(report.skipped, []);
+    }
+
+    #[test]
+    fn keeps_real_names_unless_forced() {
+        let records = [record(0x80003100, "memset2"), record(0x80005340, "zz_80005340_"), record(0x8000535c, "fn_8000535c")];
+        let (mapfile, report) = apply_str(&records, UpdateOptions::default());
+        assert_eq!(mapfile, MapFile::parse(MAP).unwrap());
+        assert_eq!(reasons(&report), [
+            SkipReason::RealName { current: "memset".into() },
+            SkipReason::PlaceholderName { current: "zz_8000535c_".into() },
+        ]);
+
+        let (mapfile, report) = apply_str(&records, UpdateOptions { force: true, ..UpdateOptions::default() });
+        assert_eq!(mapfile.entry_at(0x80003100).unwrap().1.name, "memset2");
+        assert_eq!(mapfile.entry_at(0x8000535c).unwrap().1.name, "fn_8000535c");
+        assert_eq!(report.skipped, []);
+    }
+
+    #[test]
+    fn invalid_names() {
+        let (_, report) = apply_str(&[record(0x80005340, "not valid")], UpdateOptions { force: true, ..UpdateOptions::default() });
+        assert_eq!(reasons(&report), [SkipReason::InvalidName]);
+    }
+
+    #[test]
+    fn section_mismatch() {
+        let record = UpdateRecord { section: Some(".data".into()), ..record(0x80005340, "Foo") };
+        let (_, report) = apply_str(&[record], UpdateOptions::default());
+        assert_eq!(reasons(&report), [SkipReason::SectionMismatch { actual: ".text".into() }]);
+    }
+
+    #[test]
+    fn parse_lines() {
+        assert_eq!(UpdateRecord::parse_line("80005340 Foo"), Some(record(0x80005340, "Foo")));
+
+        let parsed = UpdateRecord::parse_line("Foo 0x80005340 .data size:0x10 align=4").unwrap();
+        assert_eq!(parsed, UpdateRecord { section: Some(".data".into()), size: Some(0x10), align: Some(4), ..record(0x80005340, "Foo") });
+
+        let parsed = UpdateRecord::parse_line("80005340 000020 80005340 4 Foo").unwrap();
+        assert_eq!(parsed, UpdateRecord { size: Some(0x20), vaddr: Some(0x80005340), align: Some(4), ..record(0x80005340, "Foo") });
+    }
+
+    #[test]
+    fn parse_line_sections() {
+        let parsed = UpdateRecord::parse_line("803b7240 some_table .data").unwrap();
+        assert_eq!(parsed, UpdateRecord { section: Some(".data".into()), ..record(0x803b7240, "some_table") });
+
+        // the section is not taken as the symbol, nor found inside other tokens
+        let parsed = UpdateRecord::parse_line(".sdata 804d36a0 some_global").unwrap();
+        assert_eq!((parsed.name.as_str(), parsed.section.as_deref()), ("some_global", Some(".sdata")));
+        assert_eq!(UpdateRecord::parse_line("803b7240 some_table file.data").unwrap().section, None);
+        assert_eq!(UpdateRecord::parse_line("803b7240 some_table .note").unwrap().section, None);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let (mapfile, report) = apply_str(&[record(0x80005340, "memset")], UpdateOptions::default());
+        assert_eq!(mapfile, MapFile::parse(MAP).unwrap());
+        assert_eq!(reasons(&report), [SkipReason::DuplicateName { other_addr: 0x80003100 }]);
+
+        let (_, report) = apply_str(&[record(0x80005340, "Foo"), record(0x8000535c, "Foo")], UpdateOptions::default());
+        let mut reasons = reasons(&report);
+        reasons.sort_by_key(|r| format!("{:?}", r));
+        assert_eq!(reasons, [
+            SkipReason::DuplicateInInput { other_addr: 0x80005340 },
+            SkipReason::DuplicateInInput { other_addr: 0x8000535c },
+        ]);
+    }
+
+    #[test]
+    fn move_duplicates() {
+        let options = UpdateOptions { move_duplicates: true, ..UpdateOptions::default() };
+        let (mapfile, report) = apply_str(&[record(0x80005340, "memset")], options);
+        assert_eq!(mapfile.entry_at(0x80005340).unwrap().1.name, "memset");
+        assert_eq!(mapfile.entry_at(0x80003100).unwrap().1.name, "zz_80003100_");
+        assert_eq!(report.renamed.len(), 2);
+    }
+
+    #[test]
+    fn swapping_names_is_not_a_duplicate() {
+        let mut mapfile = MapFile::parse(MAP).unwrap();
+        apply(&mut mapfile, &[record(0x80005340, "Foo"), record(0x8000535c, "Bar")], UpdateOptions::default());
+
+        let options = UpdateOptions { force: true, ..UpdateOptions::default() };
+        let report = apply(&mut mapfile, &[record(0x80005340, "Bar"), record(0x8000535c, "Foo")], options);
+        assert_eq!(report.skipped, []);
+        assert_eq!(mapfile.entry_at(0x80005340).unwrap().1.name, "Bar");
+        assert_eq!(mapfile.entry_at(0x8000535c).unwrap().1.name, "Foo");
+    }
+
+    #[test]
+    fn insert() {
+        let options = UpdateOptions { insert: true, ..UpdateOptions::default() };
+
+        // splits the containing entry
+        let (mapfile, report) = apply_str(&[record(0x80005350, "Foo")], options);
+        assert_eq!(mapfile.entry_at(0x80005340).unwrap().1.size, 0x10);
+        assert_eq!(mapfile.entry_at(0x80005350).unwrap().1.to_string(), "80005350 00000c 80005350 0 Foo");
+        assert_eq!(report.inserted.len(), 1);
+        assert_eq!(report.resized.len(), 1);
+
+        // extends to the next entry
+        let (mapfile, _) = apply_str(&[record(0x80003200, "Bar")], options);
+        assert_eq!(mapfile.entry_at(0x80003200).unwrap().1.size, 0x80005340 - 0x80003200);
+
+        // unknown addresses are ignored without insert
+        let (mapfile, report) = apply_str(&[record(0x80003200, "Bar")], UpdateOptions::default());
+        assert_eq!(mapfile, MapFile::parse(MAP).unwrap());
+        assert_eq!(report.skipped, []);
+    }
+
+    #[test]
+    fn insert_refusals() {
+        let options = UpdateOptions { insert: true, ..UpdateOptions::default() };
+        let records = [
+            UpdateRecord { size: Some(0x2200), ..record(0x80003200, "TooBig") },
+            record(0x90000000, "Nowhere"),
+            UpdateRecord { section: Some(".data".into()), size: Some(0x10), ..record(0x80005350, "WrongSection") },
+            UpdateRecord { section: Some(".data".into()), ..record(0x803b7250, "AtTheEnd") },
+        ];
+        let (mapfile, report) = apply_str(&records, options);
+        assert_eq!(mapfile, MapFile::parse(MAP).unwrap());
+        assert_eq!(reasons(&report), [
+            SkipReason::Overlap { next: "zz_80005340_".into(), next_addr: 0x80005340 },
+            SkipReason::SectionMismatch { actual: ".text".into() },
+            SkipReason::UnknownSize,
+            SkipReason::NoSection,
+        ]);
+    }
+
+    #[test]
+    fn insert_into_named_section() {
+        let options = UpdateOptions { insert: true, ..UpdateOptions::default() };
+        let record = UpdateRecord { section: Some(".data".into()), size: Some(8), ..record(0x803b7250, "some_global") };
+        let (mapfile, report) = apply_str(&[record], options);
+        assert_eq!(report.skipped, []);
+        assert_eq!(mapfile.entry_at(0x803b7250).unwrap().0.name, ".data");
+    }
+
+    #[test]
+    fn insert_refused_names() {
+        let options = UpdateOptions { insert: true, ..UpdateOptions::default() };
+
+        // neither the placeholder entry nor the shrunk entry it was split from stay behind
+        let records = [record(0x80005350, "memset"), record(0x80003200, "not valid")];
+        let (mapfile, report) = apply_str(&records, options);
+        assert_eq!(mapfile, MapFile::parse(MAP).unwrap());
+        assert_eq!(report.inserted, []);
+        assert_eq!(report.resized, []);
+        assert_eq!(reasons(&report), [SkipReason::InvalidName, SkipReason::DuplicateName { other_addr: 0x80003100 }]);
+
+        // the other record giving the same name is still refused
+        let records = [record(0x80005350, "Foo"), record(0x8000535c, "Foo"), record(0x80003200, "Bar")];
+        let (mapfile, report) = apply_str(&records, options);
+        assert_eq!(mapfile.entry_at(0x80005340).unwrap().1.size, 0x1c);
+        assert_eq!(mapfile.entry_at(0x80005350), None);
+        assert_eq!(mapfile.entry_at(0x8000535c).unwrap().1.name, "zz_8000535c_");
+        assert_eq!(mapfile.entry_at(0x80003200).unwrap().1.name, "Bar");
+        assert_eq!(reasons(&report), [
+            SkipReason::DuplicateInInput { other_addr: 0x8000535c },
+            SkipReason::DuplicateInInput { other_addr: 0x80005350 },
+        ]);
+    }
+
+    #[test]
+    fn insert_adds_missing_section() {
+        let options = UpdateOptions { insert: true, ..UpdateOptions::default() };
+        let src = MAP.to_string() + "\n.note section layout\n";
+        let mut mapfile = MapFile::parse(&src).unwrap();
+        let records = [
+            UpdateRecord { section: Some(".sdata".into()), size: Some(4), ..record(0x804d36a0, "some_global") },
+            UpdateRecord { section: Some(".rodata".into()), size: Some(8), ..record(0x803b0000, "some_const") },
+            UpdateRecord { section: Some(".sbss".into()), ..record(0x804d7000, "NoSize") },
+        ];
+        let report = apply(&mut mapfile, &records, options);
+
+        assert_eq!(reasons(&report), [SkipReason::UnknownSize]);
+        let names = mapfile.sections.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, [".text", ".rodata", ".data", ".sdata", ".note"]);
+        assert_eq!(mapfile.entry_at(0x804d36a0).unwrap().1.to_string(), "804d36a0 000004 804d36a0 0 some_global");
+    }
+
+    #[test]
+    fn layout() {
+        let options = UpdateOptions { layout: true, ..UpdateOptions::default() };
+        let records = [
+            UpdateRecord { size: Some(0x20), align: Some(4), ..record(0x80005340, "zz_80005340_") },
+            UpdateRecord { size: Some(0x40), ..record(0x80003100, "memset") },
+            UpdateRecord { vaddr: Some(0x8000535c), align: Some(32), ..record(0x8000535c, "zz_8000535c_") },
+        ];
+        let (mapfile, report) = apply_str(&records, options);
+
+        assert_eq!(mapfile.entry_at(0x80003100).unwrap().1.size, 0x40);
+        assert_eq!(mapfile.entry_at(0x8000535c).unwrap().1.align, 32);
+        assert_eq!(report.resized.len(), 1);
+        assert_eq!(report.realigned.len(), 1);
+        assert_eq!(reasons(&report), [SkipReason::Overlap { next: "zz_8000535c_".into(), next_addr: 0x8000535c }]);
+        assert_eq!(mapfile.entry_at(0x80005340).unwrap().1.size, 0x1c);
+    }
+
+    #[test]
+    fn layout_is_ignored_without_option() {
+        let record = UpdateRecord { size: Some(0x40), align: Some(4), ..record(0x80003100, "memset") };
+        let (mapfile, report) = apply_str(&[record], UpdateOptions::default());
+        assert_eq!(mapfile, MapFile::parse(MAP).unwrap());
+        assert_eq!(report, UpdateReport::default());
+    }
+}